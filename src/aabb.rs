@@ -0,0 +1,119 @@
+use crate::{interval::Interval, ray::Ray, vec3::Point3};
+
+// Axis-aligned bounding box, stored as one interval per axis.
+#[derive(Clone, Copy, Default)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+
+    pub fn from_intervals(x: Interval, y: Interval, z: Interval) -> Self {
+        Aabb { x, y, z }
+    }
+
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        // Treat the two points a and b as extrema for the bounding box, so we don't require a
+        // particular minimum/maximum coordinate order.
+        let mut bbox = Aabb {
+            x: Interval::from_values(a.x().min(b.x()), a.x().max(b.x())),
+            y: Interval::from_values(a.y().min(b.y()), a.y().max(b.y())),
+            z: Interval::from_values(a.z().min(b.z()), a.z().max(b.z())),
+        };
+        bbox.pad_to_minimums();
+        bbox
+    }
+
+    pub fn from_boxes(box0: &Aabb, box1: &Aabb) -> Self {
+        Aabb {
+            x: Interval::from_intervals(&box0.x, &box1.x),
+            y: Interval::from_intervals(&box0.y, &box1.y),
+            z: Interval::from_intervals(&box0.z, &box1.z),
+        }
+    }
+
+    fn pad_to_minimums(&mut self) {
+        // Adjust the AABB so that no side is narrower than some delta, padding if necessary.
+        // Flat primitives such as axis-aligned triangles would otherwise produce boxes that the
+        // slab test can never hit.
+        let delta = 0.0001;
+        if self.x.size() < delta {
+            self.x = self.x.expand(delta);
+        }
+        if self.y.size() < delta {
+            self.y = self.y.expand(delta);
+        }
+        if self.z.size() < delta {
+            self.z = self.z.expand(delta);
+        }
+    }
+
+    pub fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    pub fn centroid(&self, axis: usize) -> f64 {
+        let interval = self.axis_interval(axis);
+        0.5 * (interval.min + interval.max)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        // Returns the index of the longest axis of the bounding box.
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    pub fn hit(&self, r: &Ray, mut ray_t: Interval) -> bool {
+        let ray_orig = r.origin();
+        let ray_dir = r.direction();
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / ray_dir[axis];
+
+            let t0 = (ax.min - ray_orig[axis]) * adinv;
+            let t1 = (ax.max - ray_orig[axis]) * adinv;
+
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > ray_t.min {
+                ray_t.min = t0;
+            }
+            if t1 < ray_t.max {
+                ray_t.max = t1;
+            }
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+        true
+    }
+}
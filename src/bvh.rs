@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+};
+
+// Number of buckets used when estimating the surface area heuristic for a split.
+const SAH_BUCKETS: usize = 12;
+
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(mut list: HittableList) -> Self {
+        // The list is consumed, since the objects are reordered while the tree is built.
+        Self::from_objects(&mut list.objects)
+    }
+
+    pub fn from_objects(objects: &mut [Arc<dyn Hittable>]) -> Self {
+        // Build the bounding box of the span of source objects.
+        let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, object| {
+            Aabb::from_boxes(&bbox, &object.bounding_box())
+        });
+
+        match objects.len() {
+            0 => {
+                let empty: Arc<dyn Hittable> = Arc::new(HittableList::new());
+                BvhNode {
+                    left: empty.clone(),
+                    right: empty,
+                    bbox,
+                }
+            }
+            1 => BvhNode {
+                left: objects[0].clone(),
+                right: objects[0].clone(),
+                bbox,
+            },
+            2 => BvhNode {
+                left: objects[0].clone(),
+                right: objects[1].clone(),
+                bbox,
+            },
+            _ => {
+                let mid = partition(objects, |object| object.bounding_box());
+                let (left_objects, right_objects) = objects.split_at_mut(mid);
+                BvhNode {
+                    left: Self::subtree(left_objects),
+                    right: Self::subtree(right_objects),
+                    bbox,
+                }
+            }
+        }
+    }
+
+    fn subtree(objects: &mut [Arc<dyn Hittable>]) -> Arc<dyn Hittable> {
+        // Avoid wrapping single objects in a node that would test them twice.
+        if objects.len() == 1 {
+            objects[0].clone()
+        } else {
+            Arc::new(Self::from_objects(objects))
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(r, ray_t, rec);
+        let right_max = if hit_left { rec.t } else { ray_t.max };
+        let hit_right = self
+            .right
+            .hit(r, Interval::from_values(ray_t.min, right_max), rec);
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+pub(crate) fn partition<T>(items: &mut [T], bounding_box: impl Fn(&T) -> Aabb) -> usize {
+    // Sort the items along the longest axis of their centroids, then pick the split with the
+    // lowest surface area heuristic cost, falling back to the median when the centroids are
+    // degenerate. Returns the number of items that go into the left subtree.
+    let boxes: Vec<Aabb> = items.iter().map(&bounding_box).collect();
+    let centroid_bounds = boxes.iter().fold(Aabb::EMPTY, |bbox, item_box| {
+        let centroid = |axis| {
+            let c = item_box.centroid(axis);
+            Interval::from_values(c, c)
+        };
+        Aabb::from_boxes(
+            &bbox,
+            &Aabb::from_intervals(centroid(0), centroid(1), centroid(2)),
+        )
+    });
+    let axis = centroid_bounds.longest_axis();
+    items.sort_by(|a, b| {
+        bounding_box(a)
+            .centroid(axis)
+            .total_cmp(&bounding_box(b).centroid(axis))
+    });
+
+    let extent = centroid_bounds.axis_interval(axis);
+    if extent.size() <= 0.0 {
+        return items.len() / 2;
+    }
+
+    // Count how many items fall in each bucket, then evaluate the cost of splitting after each
+    // bucket boundary.
+    let mut counts = [0usize; SAH_BUCKETS];
+    let mut bucket_boxes = [Aabb::EMPTY; SAH_BUCKETS];
+    for item_box in &boxes {
+        let offset = (item_box.centroid(axis) - extent.min) / extent.size();
+        let b = ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1);
+        counts[b] += 1;
+        bucket_boxes[b] = Aabb::from_boxes(&bucket_boxes[b], item_box);
+    }
+
+    let mut best_cost = f64::INFINITY;
+    let mut best_split = items.len() / 2;
+    let mut left_count = 0;
+    let mut left_box = Aabb::EMPTY;
+    for split in 0..SAH_BUCKETS - 1 {
+        left_count += counts[split];
+        left_box = Aabb::from_boxes(&left_box, &bucket_boxes[split]);
+        let (right_count, right_box) = (split + 1..SAH_BUCKETS)
+            .fold((0, Aabb::EMPTY), |(count, bbox), b| {
+                (count + counts[b], Aabb::from_boxes(&bbox, &bucket_boxes[b]))
+            });
+        if left_count == 0 || right_count == 0 {
+            continue;
+        }
+        let cost = left_count as f64 * left_box.surface_area()
+            + right_count as f64 * right_box.surface_area();
+        if cost < best_cost {
+            best_cost = cost;
+            best_split = left_count;
+        }
+    }
+
+    best_split
+}
@@ -1,17 +1,54 @@
 use core::f64;
 use std::f32::consts::PI;
+use std::sync::Arc;
 
-use crate::color::write_color;
+use crate::environment::EnvironmentMap;
+use crate::framebuffer::Framebuffer;
 use crate::hittable::*;
+use crate::hittable_list::HittableList;
 use crate::interval::*;
+use crate::light::Light;
+use crate::material::ScatterRecord;
+use crate::pdf::{EnvironmentPdf, HittablePdf, MixturePdf, Pdf};
+use crate::random::{self, *};
 use crate::ray::Ray;
+use crate::spectrum::{self, Wavelengths};
 use crate::vec3::*;
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
-use rand::thread_rng;
-use rand::Rng;
 use rayon::prelude::*;
 
+// What rays that escape the scene see.
+#[derive(Clone)]
+pub enum Background {
+    // The white-to-blue gradient of the original renderer, which also lights the scene.
+    Sky,
+    Solid(Color),
+    // An image of the surroundings, which is sampled like the lights.
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction: Vec3 = r.direction().normalized();
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Solid(color) => *color,
+            Background::Environment(map) => map.value(r.direction()),
+        }
+    }
+
+    pub fn environment(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Background::Environment(map) => Some(map),
+            _ => None,
+        }
+    }
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: u32,
@@ -29,11 +66,31 @@ pub struct Camera {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub background: Background,
+    // The interval during which the shutter is open. Every ray is sent at a random instant
+    // within it, blurring objects that move in the meantime.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    // Whether light sampling and material sampling are combined by multiple importance
+    // sampling, rather than leaving the lights to light sampling alone.
+    pub mis: bool,
+    // Number of bounces every path makes before Russian roulette may end it, as long as it
+    // stays within `max_depth`.
+    pub min_bounces: u32,
+    // Whether rays carry wavelengths of light rather than RGB colors, which lets glass with a
+    // dispersive refractive index split light into its colors.
+    pub spectral: bool,
+    // Whether `render` reports its progress on stderr.
+    pub show_progress: bool,
+    // Seeds the random numbers of every pixel, making renders reproducible regardless of how
+    // the work is spread over threads. Renders are seeded from the OS when unset.
+    pub seed: Option<u64>,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         image_width: u32,
@@ -46,7 +103,86 @@ impl Camera {
         defocus_angle: f64,
         focus_dist: f64,
     ) -> Self {
-        let mut image_height = (image_width as f64 / aspect_ratio) as u32;
+        let mut camera = Camera {
+            aspect_ratio,
+            image_width,
+            image_height: 1,
+            samples_per_pixel,
+            center: Point3::default(),
+            pixel_delta_u: Vec3::default(),
+            pixel_delta_v: Vec3::default(),
+            pixel00_loc: Point3::default(),
+            pixel_samples_scale: 1.0,
+            max_depth,
+            vfov,
+            lookfrom,
+            lookat,
+            vup,
+            defocus_angle,
+            focus_dist,
+            background: Background::Sky,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            mis: true,
+            min_bounces: 3,
+            spectral: false,
+            show_progress: false,
+            seed: None,
+            defocus_disk_u: Vec3::default(),
+            defocus_disk_v: Vec3::default(),
+        };
+        camera.initialize();
+        camera
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // Checks the public settings for values that would make `initialize` panic or the
+        // render come out NaN. Call this before `initialize` on settings from the user.
+        let is_positive = |x: f64| x.is_finite() && x > 0.0;
+        let is_finite = |v: Vec3| v.x().is_finite() && v.y().is_finite() && v.z().is_finite();
+        if !is_positive(self.aspect_ratio) {
+            return Err("aspect_ratio must be positive".to_string());
+        }
+        if self.image_width == 0 {
+            return Err("image_width must be at least 1".to_string());
+        }
+        if self.samples_per_pixel == 0 {
+            return Err("samples_per_pixel must be at least 1".to_string());
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return Err(format!(
+                "vfov must be between 0 and 180 degrees, got {}",
+                self.vfov
+            ));
+        }
+        if !(is_finite(self.lookfrom) && is_finite(self.lookat) && is_finite(self.vup)) {
+            return Err("lookfrom, lookat and vup must be finite".to_string());
+        }
+        if self.lookfrom == self.lookat {
+            return Err("lookfrom and lookat must differ".to_string());
+        }
+        if self.vup.cross(self.lookfrom - self.lookat).length_squared() == 0.0 {
+            return Err("vup must not be parallel to the view direction".to_string());
+        }
+        if !(self.defocus_angle.is_finite() && self.defocus_angle >= 0.0) {
+            return Err("defocus_angle must not be negative".to_string());
+        }
+        if !is_positive(self.focus_dist) {
+            return Err("focus_dist must be positive".to_string());
+        }
+        if !(self.shutter_open.is_finite() && self.shutter_close.is_finite()) {
+            return Err("shutter_open and shutter_close must be finite".to_string());
+        }
+        if self.shutter_close < self.shutter_open {
+            return Err("shutter_close must not come before shutter_open".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn initialize(&mut self) {
+        // Recomputes the viewport from the public settings. Call this after changing any of
+        // them on an existing camera.
+        let mut image_height = (self.image_width as f64 / self.aspect_ratio) as u32;
         image_height = {
             if image_height < 1 {
                 1
@@ -55,52 +191,52 @@ impl Camera {
             }
         };
         // Calculate the vectors across the horizontal and down the vertical viewport edges. sg
-        let theta = degrees_to_radians(vfov);
+        let theta = degrees_to_radians(self.vfov);
         let h = f64::tan(theta / 2.0);
-        let viewport_height: f64 = 2.0 * h * focus_dist;
-        let viewport_width: f64 = viewport_height * ((image_width as f64) / (image_height as f64));
-        let w = (lookfrom - lookat).normalized();
-        let u = (vup.cross(w)).normalized();
+        let viewport_height: f64 = 2.0 * h * self.focus_dist;
+        let viewport_width: f64 =
+            viewport_height * ((self.image_width as f64) / (image_height as f64));
+        let w = (self.lookfrom - self.lookat).normalized();
+        let u = (self.vup.cross(w)).normalized();
         let v = w.cross(u);
         let viewport_u = viewport_width * u;
         let viewport_v = viewport_height * -v;
-        let center = lookfrom;
+        let center = self.lookfrom;
         // Calculate the location of the upper left pixel.
-        let viewport_upper_left = center - (focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
-        let pixel_delta_u = viewport_u / image_width as f64;
+        let viewport_upper_left =
+            center - (self.focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
+        let pixel_delta_u = viewport_u / self.image_width as f64;
         let pixel_delta_v = viewport_v / image_height as f64;
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
         let defocus_radius =
-            focus_dist as f64 * f64::tan(degrees_to_radians(defocus_angle as f64 / 2.0));
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
-        Camera {
-            aspect_ratio,
-            image_width,
-            image_height,
-            samples_per_pixel,
-            center,
-            pixel_delta_u,
-            pixel_delta_v,
-            pixel00_loc,
-            pixel_samples_scale: 1.0 / samples_per_pixel as f64,
-            max_depth,
-            vfov,
-            lookfrom,
-            lookat,
-            vup,
-            defocus_angle,
-            focus_dist,
-            defocus_disk_u,
-            defocus_disk_v,
-        }
+            self.focus_dist * f64::tan(degrees_to_radians(self.defocus_angle / 2.0));
+
+        self.image_height = image_height;
+        self.center = center;
+        self.pixel_delta_u = pixel_delta_u;
+        self.pixel_delta_v = pixel_delta_v;
+        self.pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
+        self.defocus_disk_u = u * defocus_radius;
+        self.defocus_disk_v = v * defocus_radius;
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
-        // Render
-        println!("P3\n{0} {1}\n255", self.image_width, self.image_height);
+    pub fn render(
+        &self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        analytic_lights: &[Arc<dyn Light>],
+    ) -> Framebuffer {
+        // The lights are the emissive objects of the world that are worth sampling directly,
+        // which greatly reduces the noise from small lights. Pass an empty list for none. An
+        // environment map background is sampled along with them. Analytic lights, such as
+        // point lights, are not part of the world and only light it through shadow rays.
+        let mut image = Framebuffer::new(self.image_width, self.image_height);
 
-        let bar = ProgressBar::new((self.image_width * self.image_height) as u64);
+        let bar = if self.show_progress {
+            ProgressBar::new(self.image_width as u64 * self.image_height as u64)
+        } else {
+            ProgressBar::hidden()
+        };
         bar.set_style(
             ProgressStyle::with_template(
                 "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
@@ -110,56 +246,264 @@ impl Camera {
         );
         for j in 0..self.image_height {
             bar.inc(self.image_width.into());
-            let scanline: Vec<Color> = (0..self.image_width)
-                .into_par_iter()
-                .map(|i| {
+            image
+                .row_mut(j)
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(i, pixel)| {
+                    if let Some(seed) = self.seed {
+                        random::seed(random::mix_seed(seed, i as u64, j as u64));
+                    }
                     let mut pixel_color = Color::default();
                     for _ in 0..self.samples_per_pixel {
-                        let r: Ray = self.get_ray(i, j);
-                        pixel_color += Self::ray_color(&r, self.max_depth, world);
+                        let r: Ray = self.get_ray(i as u32, j);
+                        pixel_color +=
+                            self.ray_color(&r, self.max_depth, world, lights, analytic_lights);
                     }
-                    self.pixel_samples_scale * pixel_color
-                })
-                .collect();
-            for pixel in scanline {
-                write_color(pixel);
-            }
+                    *pixel = self.pixel_samples_scale * pixel_color;
+                    if self.spectral {
+                        // Samples stay signed while they are summed, so that the out of gamut
+                        // colors of single wavelengths average out. What is left is a color
+                        // sRGB can't show, such as that of a pure spectral line, which is
+                        // clipped to the gamut rather than written out negative.
+                        for c in 0..3 {
+                            pixel[c] = pixel[c].max(0.0);
+                        }
+                    }
+                });
         }
         bar.finish();
+
+        image
     }
 
-    pub fn ray_color(r: &Ray, depth: u32, world: &(impl Hittable + ?Sized)) -> Color {
-        // If we've exceeded the ray bounce limit, no more light is gathered.
-        if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
-        }
+    pub fn ray_color(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &(impl Hittable + ?Sized),
+        lights: &HittableList,
+        analytic_lights: &[Arc<dyn Light>],
+    ) -> Color {
+        // Follows the path of the ray through at most `depth` bounces, gathering the light it
+        // picks up along the way, weighted by the throughput of the bounces before. Rays with a
+        // wavelength are traced spectrally: their colors hold the light at three wavelengths,
+        // RGB colors of the scene are upsampled to them, and the result is converted back.
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        // The densities of the material and of the light sampling at the origin of the ray for
+        // its direction, where the lights were also sampled directly. It is None for rays from
+        // the camera or from specular materials.
+        let mut scatter_pdf: Option<(f64, f64)> = None;
+        let environment = self.background.environment().map(EnvironmentPdf::new);
+        // The absorption coefficients of the media the ray is inside, innermost last, such as
+        // tinted glass.
+        let mut media: Vec<Color> = Vec::new();
+        let wavelengths = r.wavelength().map(Wavelengths::from_hero);
+        let spectrum = |color: Color| wavelengths.map_or(color, |w| w.upsample(color));
+        // Set once the path only carries the hero wavelength.
+        let mut single_wavelength = false;
+
+        for bounce in 0..depth {
+            let mut rec: HitRecord = HitRecord::default();
+
+            // Emitters that the light sampling at the origin could also have reached share
+            // their light with it.
+            let emission_weight = match scatter_pdf {
+                Some((scatter_pdf, light_pdf_value)) => {
+                    self.light_path_weight(scatter_pdf, light_pdf_value)
+                }
+                None => 1.0,
+            };
+
+            // If the ray hits nothing, it sees the background.
+            if !world.hit(&ray, Interval::from_values(0.001, f64::INFINITY), &mut rec) {
+                if emission_weight > 0.0 {
+                    color += emission_weight * throughput * spectrum(self.background.color(&ray));
+                }
+                break;
+            }
+
+            // The medium absorbs some of the light on its way from the hit point.
+            if let Some(&absorption) = media.last() {
+                throughput = throughput
+                    * spectrum(transmittance(absorption, rec.t * ray.direction().length()));
+            }
+
+            if emission_weight > 0.0 {
+                color += emission_weight * throughput * spectrum(rec.mat.emitted(&ray, &rec));
+            }
+
+            let Some(srec) = rec.mat.scatter(&ray, &rec) else {
+                break;
+            };
+            // The material treated the hero wavelength its own way, so the others can't follow.
+            // The hero's light stands in for all three, which keeps the estimate unbiased.
+            if wavelengths.is_some() && !single_wavelength && rec.mat.is_spectral() {
+                throughput = Color::new(3.0 * throughput.x(), 0.0, 0.0);
+                single_wavelength = true;
+            }
+            let pdf = match srec {
+                ScatterRecord::Specular {
+                    attenuation,
+                    ray: scattered,
+                } => {
+                    throughput = throughput * spectrum(attenuation);
+                    enter_or_leave(&mut media, &rec, scattered.direction());
+                    ray = Ray {
+                        wavelength: r.wavelength(),
+                        ..scattered
+                    };
+                    scatter_pdf = None;
+                    if !self.survives_roulette(bounce, &mut throughput) {
+                        break;
+                    }
+                    continue;
+                }
+                ScatterRecord::Pdf(pdf) => pdf,
+            };
+
+            // Next event estimation: send a shadow ray towards a random point on the lights, or
+            // towards a bright part of the environment map.
+            let area_lights = HittablePdf::new(lights, rec.p);
+            let both;
+            let light_pdf: Option<&dyn Pdf> = match (lights.objects.is_empty(), &environment) {
+                (true, None) => None,
+                (true, Some(environment)) => Some(environment),
+                (false, None) => Some(&area_lights),
+                (false, Some(environment)) => {
+                    both = MixturePdf::new(&area_lights, environment);
+                    Some(&both)
+                }
+            };
+            if let Some(light_pdf) = light_pdf {
+                let light_ray = Ray::with_time(rec.p, light_pdf.generate(), ray.time());
+                let light_pdf_value = light_pdf.value(light_ray.direction());
+                let f = if light_pdf_value > 0.0 {
+                    spectrum(rec.mat.eval(&ray, &rec, &light_ray))
+                } else {
+                    Color::default()
+                };
+                if f != Color::default() {
+                    let mut light_rec = HitRecord::default();
+                    let absorption = absorption_towards(&media, &rec, light_ray.direction());
+                    let emitted = if world.hit(
+                        &light_ray,
+                        Interval::from_values(0.001, f64::INFINITY),
+                        &mut light_rec,
+                    ) {
+                        let distance = light_rec.t * light_ray.direction().length();
+                        spectrum(transmittance(absorption, distance))
+                            * spectrum(light_rec.mat.emitted(&light_ray, &light_rec))
+                    } else {
+                        spectrum(transmittance(absorption, f64::INFINITY))
+                            * spectrum(self.background.color(&light_ray))
+                    };
+                    let weight =
+                        self.light_sample_weight(light_pdf_value, pdf.value(light_ray.direction()));
+                    color += weight * throughput * f * emitted / light_pdf_value;
+                }
+            }
 
-        let mut rec: HitRecord = HitRecord::default();
+            // Analytic lights can't be hit by the path, so shadow rays are the only way to them
+            // and their light needs no weighting.
+            for light in analytic_lights {
+                let Some(sample) = light.sample(rec.p) else {
+                    continue;
+                };
+                let light_ray = Ray::with_time(rec.p, sample.direction, ray.time());
+                let f = spectrum(rec.mat.eval(&ray, &rec, &light_ray));
+                let mut light_rec = HitRecord::default();
+                if f != Color::default()
+                    && !world.hit(
+                        &light_ray,
+                        Interval::from_values(0.001, sample.distance),
+                        &mut light_rec,
+                    )
+                {
+                    let absorption = absorption_towards(&media, &rec, sample.direction);
+                    color += throughput
+                        * f
+                        * spectrum(transmittance(absorption, sample.distance))
+                        * spectrum(sample.radiance);
+                }
+            }
 
-        if world.hit(r, Interval::from_values(0.001, f64::INFINITY), &mut rec) {
-            if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-                return attenuation * Camera::ray_color(&scattered, depth - 1, world);
+            // Then continue the path in a direction sampled from the material.
+            let mut scattered = Ray::with_time(rec.p, pdf.generate(), ray.time());
+            scattered.wavelength = r.wavelength();
+            let pdf_value = pdf.value(scattered.direction());
+            if pdf_value <= 0.0 {
+                break;
+            }
+            throughput = throughput * spectrum(rec.mat.eval(&ray, &rec, &scattered)) / pdf_value;
+            enter_or_leave(&mut media, &rec, scattered.direction());
+            ray = scattered;
+            let light_pdf_value = light_pdf.map_or(0.0, |p| p.value(scattered.direction()));
+            scatter_pdf = Some((pdf_value, light_pdf_value));
+            if !self.survives_roulette(bounce, &mut throughput) {
+                break;
             }
-            return Color::new(0.0, 0.0, 0.0);
         }
 
-        let unit_direction: Vec3 = r.direction().normalized();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        wavelengths.map_or(color, |w| w.to_rgb(color))
+    }
+
+    fn survives_roulette(&self, bounce: u32, throughput: &mut Color) -> bool {
+        // Russian roulette: past the first few bounces, end paths at random, more likely the
+        // less light they can still carry, and scale up the ones that go on so that the result
+        // stays unbiased.
+        if bounce + 1 < self.min_bounces {
+            return true;
+        }
+        let survival = throughput
+            .x()
+            .max(throughput.y())
+            .max(throughput.z())
+            .min(1.0);
+        if survival <= 0.0 || random_double() >= survival {
+            return false;
+        }
+        *throughput /= survival;
+        true
+    }
+
+    fn light_sample_weight(&self, light_pdf_value: f64, scatter_pdf_value: f64) -> f64 {
+        // The weight of light found by sampling the lights, when the material could also have
+        // sampled the same direction.
+        if self.mis {
+            power_heuristic(light_pdf_value, scatter_pdf_value)
+        } else {
+            1.0
+        }
+    }
+
+    fn light_path_weight(&self, scatter_pdf_value: f64, light_pdf_value: f64) -> f64 {
+        // The weight of light found by following the material's direction, when sampling the
+        // lights could also have picked it. Without MIS, that light is left to light sampling.
+        if light_pdf_value <= 0.0 {
+            1.0
+        } else if self.mis {
+            power_heuristic(scatter_pdf_value, light_pdf_value)
+        } else {
+            0.0
+        }
     }
 
     fn sample_square() -> Vec3 {
         // Returns the vector to a random point in the [-.5,-.5]-[+.5,+.5] unit square.
         Vec3::new(
-            thread_rng().gen_range(0.0..0.999) - 0.5,
-            thread_rng().gen_range(0.0..0.999) - 0.5,
+            random_double_range(0.0, 0.999) - 0.5,
+            random_double_range(0.0, 0.999) - 0.5,
             0.0,
         )
     }
 
     fn get_ray(&self, i: u32, j: u32) -> Ray {
         // Construct a camera ray originating from the defocus disk and directed at a randomly
-        // sampled point around the pixel location i, j.
+        // sampled point around the pixel location i, j, sent at a random time while the
+        // shutter is open.
         let offset = Self::sample_square();
         let pixel_sample = self.pixel00_loc
             + ((i as f64 + offset.x()) * self.pixel_delta_u)
@@ -171,8 +515,17 @@ impl Camera {
             self.defocus_disk_sample()
         };
         let ray_direction = pixel_sample - ray_origin;
+        let ray_time = if self.shutter_close > self.shutter_open {
+            random_double_range(self.shutter_open, self.shutter_close)
+        } else {
+            self.shutter_open
+        };
 
-        Ray::new(ray_origin, ray_direction)
+        let mut ray = Ray::with_time(ray_origin, ray_direction, ray_time);
+        if self.spectral {
+            ray.wavelength = Some(spectrum::sample_wavelength());
+        }
+        ray
     }
 
     fn defocus_disk_sample(&self) -> Point3 {
@@ -181,6 +534,276 @@ impl Camera {
         self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
     }
 }
+
+fn enter_or_leave(media: &mut Vec<Color>, rec: &HitRecord, direction: Vec3) {
+    // Rays that pass through the front face of a material with an interior enter its medium,
+    // and those that pass through the back face leave it. The normal faces the incoming ray.
+    if rec.normal.dot(direction) >= 0.0 {
+        return;
+    }
+    if let Some(absorption) = rec.mat.absorption() {
+        if rec.front_face {
+            media.push(absorption);
+        } else {
+            media.pop();
+        }
+    }
+}
+
+fn absorption_towards(media: &[Color], rec: &HitRecord, direction: Vec3) -> Color {
+    // The absorption coefficient of the medium a ray from the hit point in the given direction
+    // travels through, without changing the media the path is inside.
+    let clear = Color::new(0.0, 0.0, 0.0);
+    let outer = |depth: usize| media.len().checked_sub(depth).map_or(clear, |i| media[i]);
+    match rec.mat.absorption() {
+        Some(absorption) if rec.normal.dot(direction) < 0.0 => {
+            if rec.front_face {
+                absorption
+            } else {
+                outer(2)
+            }
+        }
+        _ => outer(1),
+    }
+}
+
+fn transmittance(absorption: Color, distance: f64) -> Color {
+    // The Beer-Lambert law: the fraction of light left after the given distance through a
+    // medium.
+    let channel = |a: f64| if a > 0.0 { (-a * distance).exp() } else { 1.0 };
+    Color::new(
+        channel(absorption.x()),
+        channel(absorption.y()),
+        channel(absorption.z()),
+    )
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    // Veach's power heuristic (with an exponent of two) for combining two sampling strategies.
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
 fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI as f64 / 180.0
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        hittable_list::HittableList,
+        light::PointLight,
+        material::{Dielectric, DiffuseLight, Lambertian, Metal, Scatter},
+        quad::{make_box, Quad},
+        sphere::Sphere,
+    };
+
+    fn camera(background: Background) -> Camera {
+        let mut camera = Camera::new(
+            1.0,
+            10,
+            1,
+            10,
+            90.0,
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+        );
+        camera.background = background;
+        camera
+    }
+
+    fn radiance(camera: &Camera, r: &Ray, depth: u32, world: &HittableList) -> Color {
+        camera.ray_color(r, depth, world, &HittableList::new(), &[])
+    }
+
+    #[test]
+    fn escaping_rays_see_the_background() {
+        let world = HittableList::new();
+        let up = Ray::new(Point3::default(), Vec3::new(0.0, 1.0, 0.0));
+        let sky = radiance(&camera(Background::Sky), &up, 10, &world);
+        assert!((sky - Color::new(0.5, 0.7, 1.0)).length() < 1e-12);
+
+        let color = Color::new(0.1, 0.2, 0.3);
+        let solid = radiance(&camera(Background::Solid(color)), &up, 10, &world);
+        assert_eq!(solid, color);
+        // Paths that run out of bounces gather nothing.
+        let black = Color::new(0.0, 0.0, 0.0);
+        assert_eq!(
+            radiance(&camera(Background::Solid(color)), &up, 0, &world),
+            black
+        );
+    }
+
+    #[test]
+    fn lights_emit_from_their_front_face() {
+        let emit = Color::new(4.0, 2.0, 1.0);
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(DiffuseLight::new(emit)),
+        )));
+        let camera = camera(Background::Solid(Color::new(0.5, 0.5, 0.5)));
+
+        let outside = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(radiance(&camera, &outside, 10, &world), emit);
+        let inside = Ray::new(Point3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(radiance(&camera, &inside, 10, &world), Color::default());
+    }
+
+    #[test]
+    fn point_lights_light_diffuse_surfaces() {
+        // A point light 2 above a white floor, seen at an angle. Bounces off the floor escape
+        // into the black background, so only the direct light is left: the albedo over pi
+        // times the intensity, the cosine at the floor and the inverse squared distance.
+        let mut world = HittableList::new();
+        world.add(Arc::new(Quad::new(
+            Point3::new(-10.0, 0.0, 10.0),
+            Vec3::new(20.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -20.0),
+            Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))),
+        )));
+        let analytic_lights: [Arc<dyn Light>; 1] = [Arc::new(PointLight::new(
+            Point3::new(0.0, 2.0, 0.0),
+            Color::new(10.0, 10.0, 10.0),
+        ))];
+        let camera = camera(Background::Solid(Color::default()));
+
+        // The ray hits the floor at (1, 0, 0).
+        let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let color = camera.ray_color(&r, 10, &world, &HittableList::new(), &analytic_lights);
+        let distance_squared: f64 = 5.0;
+        let cosine = 2.0 / distance_squared.sqrt();
+        let expected = 10.0 / std::f64::consts::PI * cosine / distance_squared;
+        assert!((color.x() - expected).abs() < 1e-9, "{color:?}");
+    }
+
+    #[test]
+    fn tinted_glass_absorbs_by_beer_lambert() {
+        // A tinted block from z = -1 to -5, with a clear ball of radius 1 inside it. Their
+        // refractive index of 1 lets the ray straight through, so it travels 2 units through
+        // the tint and 2 through the ball.
+        let absorption = Color::new(0.5, 0.2, 0.1);
+        let mut world = HittableList::new();
+        world.add(Arc::new(make_box(
+            Point3::new(-1.0, -1.0, -5.0),
+            Point3::new(1.0, 1.0, -1.0),
+            Arc::new(Dielectric::with_absorption(1.0, absorption)),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(Dielectric::new(1.0)),
+        )));
+        let mut camera = camera(Background::Solid(Color::new(1.0, 1.0, 1.0)));
+        // Russian roulette would make the result random.
+        camera.min_bounces = 10;
+
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0));
+        let color = radiance(&camera, &r, 10, &world);
+        for i in 0..3 {
+            let expected = (-2.0 * absorption[i]).exp();
+            assert!((color[i] - expected).abs() < 1e-9, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn light_sampling_keeps_the_expected_value() {
+        // A floor lit by a small lamp above it, seen with and without sampling the lamp
+        // directly, and with and without multiple importance sampling. All must converge to the
+        // same color, without counting the lamp twice.
+        crate::random::seed(3);
+        let lamp = Arc::new(Quad::new(
+            Point3::new(-0.25, 1.0, -0.25),
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.5),
+            Arc::new(DiffuseLight::new(Color::new(8.0, 8.0, 8.0))),
+        ));
+        let mut lights = HittableList::new();
+        lights.add(lamp.clone());
+        let floors: [Arc<dyn Scatter>; 2] = [
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            Arc::new(Metal::new(Color::new(0.5, 0.5, 0.5), 0.5)),
+        ];
+        for floor in floors {
+            let mut world = HittableList::new();
+            world.add(Arc::new(Quad::new(
+                Point3::new(-10.0, 0.0, 10.0),
+                Vec3::new(20.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -20.0),
+                floor,
+            )));
+            world.add(lamp.clone());
+
+            let mut camera = camera(Background::Solid(Color::default()));
+            let r = Ray::new(Point3::new(0.0, 0.5, 0.15), Vec3::new(0.0, -1.0, -0.3));
+            let n = 40_000;
+            let mean = |camera: &Camera, lights: &HittableList| {
+                (0..n)
+                    .map(|_| camera.ray_color(&r, 10, &world, lights, &[]).x())
+                    .sum::<f64>()
+                    / n as f64
+            };
+            let unsampled = mean(&camera, &HittableList::new());
+            let mis = mean(&camera, &lights);
+            camera.mis = false;
+            let sampled = mean(&camera, &lights);
+            for (strategy, value) in [("multiple importance", mis), ("light", sampled)] {
+                assert!(
+                    (value - unsampled).abs() < 0.05 * unsampled,
+                    "{value} with {strategy} sampling, {unsampled} without"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_expected_value() {
+        // A lamp inside a closed gray box, where paths bounce around for a long time.
+        crate::random::seed(4);
+        let walls = Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7)));
+        let lamp = Arc::new(Quad::new(
+            Point3::new(-0.25, 0.99, -0.25),
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.5),
+            Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+        ));
+        let mut world = HittableList::new();
+        world.add(Arc::new(make_box(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            walls,
+        )));
+        world.add(lamp.clone());
+        let mut lights = HittableList::new();
+        lights.add(lamp);
+
+        let mut camera = camera(Background::Solid(Color::default()));
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.5), Vec3::new(0.2, -0.3, -1.0));
+        let n = 20_000;
+        let mean = |camera: &Camera| {
+            (0..n)
+                .map(|_| camera.ray_color(&r, 50, &world, &lights, &[]).x())
+                .sum::<f64>()
+                / n as f64
+        };
+        camera.min_bounces = 50;
+        let full = mean(&camera);
+        camera.min_bounces = 0;
+        let roulette = mean(&camera);
+        assert!(
+            (roulette - full).abs() < 0.05 * full,
+            "{roulette} with Russian roulette, {full} without"
+        );
+    }
+}
@@ -11,15 +11,32 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
 }
 
 #[inline]
-pub fn write_color(pixel_color: Color) {
+pub fn gamma_to_linear(gamma_component: f64) -> f64 {
+    // The inverse of the gamma applied to rendered images, used to decode texture images.
+    let gamma = 2.0;
+    if gamma_component > 0.0 {
+        gamma_component.powf(gamma)
+    } else {
+        0.0
+    }
+}
+
+#[inline]
+pub fn luminance(c: Color) -> f64 {
+    // The perceived brightness of a linear color.
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+#[inline]
+pub fn to_rgb8(pixel_color: Color) -> [u8; 3] {
     let r = linear_to_gamma(pixel_color.x());
     let g = linear_to_gamma(pixel_color.y());
     let b = linear_to_gamma(pixel_color.z());
 
     let intensity: Interval = Interval::from_values(0.000, 0.999);
-    let rbyte = (256.0 * intensity.clamp(r)) as u32;
-    let gbyte = (256.0 * intensity.clamp(g)) as u32;
-    let bbyte = (256.0 * intensity.clamp(b)) as u32;
+    let rbyte = (256.0 * intensity.clamp(r)) as u8;
+    let gbyte = (256.0 * intensity.clamp(g)) as u8;
+    let bbyte = (256.0 * intensity.clamp(b)) as u8;
 
-    println!("{rbyte} {gbyte} {bbyte}")
+    [rbyte, gbyte, bbyte]
 }
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{Isotropic, Scatter},
+    random::random_double,
+    ray::Ray,
+    texture::Texture,
+    vec3::{Color, Vec3},
+};
+
+// A volume of constant density filling a boundary object, such as smoke or fog. Rays passing
+// through it scatter at random distances, more often the denser the medium.
+//
+// The boundary must be closed and convex: a ray is assumed to enter and leave it only once.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Scatter>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn from_texture(boundary: Arc<dyn Hittable>, density: f64, tex: Arc<dyn Texture>) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::from_texture(tex)))
+    }
+
+    pub fn with_phase_function(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Scatter>,
+    ) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // Find where the ray enters and leaves the boundary, along the whole line.
+        let mut rec1 = HitRecord::default();
+        let mut rec2 = HitRecord::default();
+
+        if !self.boundary.hit(r, Interval::UNIVERSE, &mut rec1) {
+            return false;
+        }
+        if !self.boundary.hit(
+            r,
+            Interval::from_values(rec1.t + 0.0001, f64::INFINITY),
+            &mut rec2,
+        ) {
+            return false;
+        }
+
+        // Only the part of the ray inside both the medium and the ray interval counts.
+        rec1.t = rec1.t.max(ray_t.min);
+        rec2.t = rec2.t.min(ray_t.max);
+        if rec1.t >= rec2.t {
+            return false;
+        }
+        rec1.t = rec1.t.max(0.0);
+
+        // Sample the distance the ray travels before scattering, and let it pass through when
+        // that's beyond the far side of the medium.
+        let ray_length = r.direction().length();
+        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        let hit_distance = self.neg_inv_density * random_double().ln();
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = rec1.t + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+        (rec.u, rec.v) = (0.0, 0.0);
+        // The normal and face are arbitrary, since the phase function ignores them.
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.mat = self.phase_function.clone();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, quad::make_box, random, vec3::Point3};
+
+    // A medium filling the unit cube around the origin.
+    fn medium(density: f64) -> ConstantMedium {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let boundary = make_box(
+            Point3::new(-0.5, -0.5, -0.5),
+            Point3::new(0.5, 0.5, 0.5),
+            mat,
+        );
+        ConstantMedium::new(Arc::new(boundary), density, Color::new(0.8, 0.8, 0.8))
+    }
+
+    fn hit(object: &impl Hittable, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        object.hit(r, ray_t, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn scattering_follows_beer_lambert() {
+        // Rays crossing the cube scatter with probability 1 - exp(-density * 1), at points
+        // inside it.
+        random::seed(1);
+        let density = 0.7;
+        let medium = medium(density);
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let n = 100_000;
+        let mut scattered = 0;
+        for _ in 0..n {
+            if let Some(rec) = hit(&medium, &r, Interval::from_values(0.001, f64::INFINITY)) {
+                assert!(rec.p.z() >= -0.5 - 1e-9 && rec.p.z() <= 0.5 + 1e-9);
+                scattered += 1;
+            }
+        }
+        let expected = 1.0 - f64::exp(-density);
+        assert!((scattered as f64 / n as f64 - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn only_the_ray_interval_counts() {
+        random::seed(2);
+        let medium = medium(1e6);
+        // A ray starting inside scatters right away, in front of its origin.
+        let inside = Ray::new(Point3::new(0.0, 0.0, 0.25), Vec3::new(0.0, 0.0, -1.0));
+        let rec = hit(&medium, &inside, Interval::from_values(0.0, f64::INFINITY)).unwrap();
+        assert!(rec.t >= 0.0 && rec.t < 1e-3);
+        // Rays that stop before the medium or miss it pass through untouched.
+        let outside = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(hit(&medium, &outside, Interval::from_values(0.0, 4.0)).is_none());
+        let miss = Ray::new(Point3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(hit(&medium, &miss, Interval::UNIVERSE).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use std::{f64::consts::PI, path::Path};
+
+use crate::{
+    color::{gamma_to_linear, luminance},
+    random::random_double,
+    vec3::{Color, Vec3},
+};
+
+// An equirectangular image of the light arriving from every direction, such as a Radiance HDR
+// panorama. It can be sampled in proportion to its brightness, so that small bright features like
+// the sun are found by light sampling.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    // Linear colors, row by row from the top (straight up) down.
+    pixels: Vec<Color>,
+    // Scales the brightness of the map.
+    pub intensity: f64,
+    // Turns the map about the vertical axis, in degrees.
+    pub rotation: f64,
+    // Cumulative distributions for sampling a row, then a pixel within that row, in proportion
+    // to the pixel weights. The last entry of each is the total.
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        // High dynamic range images are stored linearly; other formats are gamma decoded like
+        // image textures.
+        let path = path.as_ref();
+        let is_hdr = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("hdr"));
+        let image = image::open(path)?.into_rgb32f();
+        let decode = |c: f32| {
+            if is_hdr {
+                c as f64
+            } else {
+                gamma_to_linear(c as f64)
+            }
+        };
+        let pixels = image
+            .pixels()
+            .map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2])))
+            .collect();
+        Ok(Self::from_pixels(
+            image.width() as usize,
+            image.height() as usize,
+            pixels,
+        ))
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height);
+
+        let mut map = EnvironmentMap {
+            width,
+            height,
+            pixels,
+            intensity: 1.0,
+            rotation: 0.0,
+            row_cdf: Vec::with_capacity(height),
+            column_cdfs: Vec::with_capacity(width * height),
+        };
+
+        let mut total = 0.0;
+        for j in 0..height {
+            let mut row_total = 0.0;
+            for i in 0..width {
+                row_total += map.weight(i, j);
+                map.column_cdfs.push(row_total);
+            }
+            total += row_total;
+            map.row_cdf.push(total);
+        }
+
+        map
+    }
+
+    fn weight(&self, i: usize, j: usize) -> f64 {
+        // Pixels near the poles cover less solid angle, in proportion to the sine of their
+        // angle from the vertical.
+        let sin_theta = (PI * (j as f64 + 0.5) / self.height as f64).sin();
+        luminance(self.pixels[j * self.width + i]).max(0.0) * sin_theta
+    }
+
+    fn total_weight(&self) -> f64 {
+        *self.row_cdf.last().unwrap()
+    }
+
+    fn to_local(&self, direction: Vec3, sign: f64) -> Vec3 {
+        // Undoes the rotation of the map for a sign of 1, and applies it for -1.
+        let (sin, cos) = (sign * self.rotation).to_radians().sin_cos();
+        Vec3::new(
+            cos * direction.x() + sin * direction.z(),
+            direction.y(),
+            -sin * direction.x() + cos * direction.z(),
+        )
+    }
+
+    fn pixel_of(&self, direction: Vec3) -> (usize, usize, f64) {
+        // Returns the pixel seen in a direction, and the sine of its angle from the vertical.
+        // The horizontal image coordinate goes around the vertical axis like the texture
+        // coordinates of a sphere, with the middle of the image facing +x.
+        let d = self.to_local(direction, 1.0).normalized();
+        let u = ((-d.z()).atan2(d.x()) + PI) / (2.0 * PI);
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((theta / PI * self.height as f64) as usize).min(self.height - 1);
+        (i, j, theta.sin())
+    }
+
+    pub fn value(&self, direction: Vec3) -> Color {
+        let (i, j, _) = self.pixel_of(direction);
+        self.intensity * self.pixels[j * self.width + i]
+    }
+
+    pub fn pdf_value(&self, direction: Vec3) -> f64 {
+        // The density with respect to solid angle of `random` returning the direction.
+        // A black map is sampled uniformly instead.
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return 1.0 / (4.0 * PI);
+        }
+        let (i, j, sin_theta) = self.pixel_of(direction);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // Directions are uniform within a pixel, which spans 2 pi / width by pi / height in
+        // angles, and an angle patch covers sin(theta) times its area in solid angle.
+        let probability = self.weight(i, j) / total;
+        let pixel_angle_area = (2.0 * PI / self.width as f64) * (PI / self.height as f64);
+        probability / (pixel_angle_area * sin_theta)
+    }
+
+    pub fn random(&self) -> Vec3 {
+        // Returns a random direction, picking pixels in proportion to their weights.
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return Vec3::random_unit_vector();
+        }
+
+        let target = random_double() * total;
+        let j = self
+            .row_cdf
+            .partition_point(|&c| c <= target)
+            .min(self.height - 1);
+        let row = &self.column_cdfs[j * self.width..(j + 1) * self.width];
+        let target = random_double() * row[self.width - 1];
+        let i = row.partition_point(|&c| c <= target).min(self.width - 1);
+
+        let u = (i as f64 + random_double()) / self.width as f64;
+        let theta = PI * (j as f64 + random_double()) / self.height as f64;
+        let phi = 2.0 * PI * u - PI;
+        let local = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            -theta.sin() * phi.sin(),
+        );
+        self.to_local(local, -1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{tests::check_pdf, EnvironmentPdf};
+
+    // A dim map with a bright spot and a brighter horizontal band, which makes for a lumpy
+    // density to sample.
+    fn map() -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|k| {
+                let (i, j) = (k % width, k / width);
+                if (i, j) == (11, 2) {
+                    Color::new(50.0, 40.0, 30.0)
+                } else if j == 5 {
+                    Color::new(1.0, 2.0, 3.0)
+                } else {
+                    Color::new(0.1, 0.1, 0.1)
+                }
+            })
+            .collect();
+        EnvironmentMap::from_pixels(width, height, pixels)
+    }
+
+    #[test]
+    fn value_looks_up_the_pixel_in_a_direction() {
+        let mut map = map();
+        map.intensity = 2.0;
+        let dim = Color::new(0.2, 0.2, 0.2);
+        assert_eq!(map.value(Vec3::new(0.0, 1.0, 0.0)), dim);
+        // The middle of the image faces +x, and u grows towards -z like on a sphere.
+        let spot = Color::new(100.0, 80.0, 60.0);
+        let theta = PI * 2.5 / 8.0;
+        let phi = 2.0 * PI * 11.5 / 16.0 - PI;
+        let direction = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            -theta.sin() * phi.sin(),
+        );
+        assert_eq!(map.value(direction), spot);
+
+        // Turning the map turns the spot with it.
+        map.rotation = 90.0;
+        assert_ne!(map.value(direction), spot);
+        assert_eq!(map.value(map.to_local(direction, -1.0)), spot);
+    }
+
+    #[test]
+    fn pdf_value_matches_random() {
+        let mut map = map();
+        check_pdf(&EnvironmentPdf::new(&map));
+        map.rotation = 30.0;
+        check_pdf(&EnvironmentPdf::new(&map));
+    }
+
+    #[test]
+    fn black_maps_are_sampled_uniformly() {
+        let map = EnvironmentMap::from_pixels(4, 2, vec![Color::default(); 8]);
+        check_pdf(&EnvironmentPdf::new(&map));
+    }
+}
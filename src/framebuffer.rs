@@ -0,0 +1,137 @@
+use crate::{color::to_rgb8, vec3::Color};
+
+// The linear radiance of every pixel of a rendered image, stored row by row from the top left.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Color::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<Color> {
+        self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    pub fn get_rgb8(&self, x: u32, y: u32) -> [u8; 3] {
+        to_rgb8(self.get(x, y))
+    }
+
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        // Gamma-encoded 8-bit samples, three per pixel, in the same order as the pixels.
+        self.pixels.iter().flat_map(|&p| to_rgb8(p)).collect()
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        // Like `to_rgb8`, with an opaque alpha channel after each pixel.
+        self.pixels
+            .iter()
+            .flat_map(|&p| {
+                let [r, g, b] = to_rgb8(p);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn row_mut(&mut self, y: u32) -> &mut [Color] {
+        let start = y as usize * self.width as usize;
+        &mut self.pixels[start..start + self.width as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Framebuffer {
+        // A 3 by 2 image whose pixels can all be told apart.
+        let mut image = Framebuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                image.set(x, y, Color::new(x as f64 / 4.0, y as f64, 0.25));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn new_image_is_black() {
+        let image = Framebuffer::new(4, 3);
+        assert_eq!((image.width(), image.height()), (4, 3));
+        assert_eq!(image.pixels().len(), 12);
+        assert!(image.pixels().iter().all(|p| p.length_squared() == 0.0));
+    }
+
+    #[test]
+    fn pixels_are_stored_row_by_row_from_the_top_left() {
+        let image = image();
+        assert_eq!(image.get(2, 1).x(), 0.5);
+        assert_eq!(image.get(2, 1).y(), 1.0);
+        let xs: Vec<f64> = image.pixels().iter().map(|p| p.x()).collect();
+        assert_eq!(xs, [0.0, 0.25, 0.5, 0.0, 0.25, 0.5]);
+        let ys: Vec<f64> = image.into_pixels().iter().map(|p| p.y()).collect();
+        assert_eq!(ys, [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn row_mut_is_one_row() {
+        let mut image = image();
+        let row = image.row_mut(1);
+        assert_eq!(row.len(), 3);
+        row[0] = Color::new(1.0, 1.0, 1.0);
+        assert_eq!(image.get(0, 1).z(), 1.0);
+        assert_eq!(image.get(0, 0).z(), 0.25);
+    }
+
+    #[test]
+    fn rgb8_is_gamma_encoded_in_pixel_order() {
+        let image = image();
+        assert_eq!(image.get_rgb8(0, 0), [0, 0, 128]);
+        assert_eq!(image.get_rgb8(1, 1), [128, 255, 128]);
+        let rgb = image.to_rgb8();
+        assert_eq!(rgb.len(), 18);
+        assert_eq!(&rgb[..6], [0, 0, 128, 128, 0, 128]);
+        assert_eq!(&rgb[15..], image.get_rgb8(2, 1));
+        let rgba = image.to_rgba8();
+        assert_eq!(&rgba[4..8], [128, 0, 128, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "pixel out of bounds")]
+    fn out_of_bounds_pixel_panics() {
+        image().get(3, 0);
+    }
+}
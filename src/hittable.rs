@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
     interval::Interval,
     material::*,
     ray::Ray,
@@ -9,6 +10,19 @@ use crate::{
 
 pub trait Hittable: Sync + Send {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    fn bounding_box(&self) -> Aabb;
+
+    // The density, with respect to solid angle, of `random` returning the given direction from
+    // the origin. Only objects that can be sampled as lights implement this and `random`.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // Returns a random direction from the origin towards the object.
+    fn random(&self, _origin: Point3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 #[derive(Clone)]
@@ -17,6 +31,8 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub mat: Arc<dyn Scatter>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
@@ -43,6 +59,8 @@ impl Default for HitRecord {
             normal: Vec3::new(0.0, 0.0, 0.0),
             mat: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: true,
         }
     }
@@ -1,26 +1,38 @@
 use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
+    random::random_int,
+    vec3::{Point3, Vec3},
 };
 
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
     pub fn new() -> Self {
         HittableList {
             objects: Vec::new(),
+            bbox: Aabb::EMPTY,
         }
     }
 
     pub fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.bbox = Aabb::from_boxes(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Hittable for HittableList {
     fn hit(&self, r: &crate::ray::Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let mut temp_rec: HitRecord = HitRecord::default();
@@ -41,4 +53,28 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        // Each object is picked with the same probability.
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let index = random_int(0, self.objects.len() as i64 - 1) as usize;
+        self.objects[index].random(origin)
+    }
 }
@@ -6,10 +6,36 @@ pub struct Interval {
 }
 
 impl Interval {
+    pub const EMPTY: Interval = Interval {
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+    };
+
+    pub const UNIVERSE: Interval = Interval {
+        min: f64::NEG_INFINITY,
+        max: f64::INFINITY,
+    };
+
     pub fn from_values(min: f64, max: f64) -> Interval {
         Interval { min, max }
     }
 
+    pub fn from_intervals(a: &Interval, b: &Interval) -> Interval {
+        // Create the interval tightly enclosing the two input intervals.
+        Interval {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.min <= x && x <= self.max
+    }
+
     pub fn surrounds(&self, x: f64) -> bool {
         self.min < x && x < self.max
     }
@@ -23,4 +49,15 @@ impl Interval {
             x
         }
     }
+
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::from_values(self.min - padding, self.max + padding)
+    }
+}
+
+impl Default for Interval {
+    fn default() -> Self {
+        Interval::EMPTY
+    }
 }
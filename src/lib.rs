@@ -0,0 +1,60 @@
+//! A ray tracer based on Peter Shirley's "Ray Tracing in One Weekend" series.
+//!
+//! Build a world out of `Hittable` objects with `Scatter` materials, or load one with
+//! `scene::load_scene`, then hand it to a `Camera` to render it into a `Framebuffer`.
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod constant_medium;
+pub mod environment;
+pub mod framebuffer;
+pub mod hittable;
+pub mod hittable_list;
+pub mod interval;
+pub mod light;
+pub mod material;
+pub mod matrix;
+pub mod microfacet;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod perlin;
+pub mod quad;
+pub mod random;
+pub mod ray;
+pub mod scene;
+pub mod scenes;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod thin_film;
+pub mod transform;
+pub mod triangle;
+pub mod vec3;
+
+pub use bvh::BvhNode;
+pub use camera::{Background, Camera};
+pub use constant_medium::ConstantMedium;
+pub use environment::EnvironmentMap;
+pub use framebuffer::Framebuffer;
+pub use hittable::{HitRecord, Hittable};
+pub use hittable_list::HittableList;
+pub use light::{DirectionalLight, Light, PointLight, SpotLight};
+pub use material::{
+    Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Metal, MetalPreset, Principled,
+    RoughDielectric, Scatter,
+};
+pub use matrix::Mat4;
+pub use quad::{make_box, Disk, PlanarTriangle, Quad};
+pub use ray::Ray;
+pub use scene::Scene;
+pub use spectrum::RefractiveIndex;
+pub use sphere::Sphere;
+pub use texture::Texture;
+pub use thin_film::ThinFilm;
+pub use transform::Transform;
+pub use triangle::{Triangle, TriangleMesh};
+pub use vec3::{Color, Point3, Vec3};
@@ -0,0 +1,229 @@
+use std::f64::consts::PI;
+
+use crate::{
+    onb::Onb,
+    random::random_double,
+    vec3::{Color, Point3, Vec3},
+};
+
+// Light arriving at a point from one sampled direction of a light.
+pub struct LightSample {
+    // Unit direction from the point towards the light.
+    pub direction: Vec3,
+    // How far the light is along the direction, which shadow rays must get through unblocked.
+    pub distance: f64,
+    // The incident light divided by the density of the sampled direction, so that it only needs
+    // weighting by the material.
+    pub radiance: Color,
+}
+
+// A light that isn't part of the world, such as a point light. Rays never hit it; it is only
+// found by sending shadow rays towards it.
+pub trait Light: Sync + Send {
+    // Returns None if the light doesn't reach the point.
+    fn sample(&self, p: Point3) -> Option<LightSample>;
+}
+
+// Shines equally in all directions from a single point.
+pub struct PointLight {
+    position: Point3,
+    // Radiant intensity, which falls off with the squared distance.
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Point3) -> Option<LightSample> {
+        point_sample(self.position, self.intensity, p)
+    }
+}
+
+// Shines from a single point within a cone, at full intensity inside `inner_angle` of its axis
+// and fading out smoothly towards `outer_angle`.
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        // Angles are in degrees from the axis of the cone.
+        let outer_angle = outer_angle.max(inner_angle);
+        SpotLight {
+            position,
+            direction: direction.normalized(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Point3) -> Option<LightSample> {
+        let falloff = self.falloff(self.direction.dot((p - self.position).normalized()));
+        if falloff <= 0.0 {
+            return None;
+        }
+        point_sample(self.position, falloff * self.intensity, p)
+    }
+}
+
+// Light from very far away, like the sun, arriving from within a small cone of directions.
+pub struct DirectionalLight {
+    // The direction the light travels in, away from the sun.
+    direction: Vec3,
+    // The irradiance on a surface facing the light.
+    irradiance: Color,
+    cos_half_angle: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color, angular_diameter: f64) -> Self {
+        // The angular diameter is in degrees; 0 makes shadows perfectly sharp.
+        DirectionalLight {
+            direction: direction.normalized(),
+            irradiance,
+            cos_half_angle: (angular_diameter / 2.0).to_radians().cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: Point3) -> Option<LightSample> {
+        // Directions are uniform over the cone the sun covers. Its radiance is the irradiance
+        // spread over that solid angle, so dividing by the density leaves the irradiance.
+        let uvw = Onb::new(-self.direction);
+        let z = 1.0 + random_double() * (self.cos_half_angle - 1.0);
+        let phi = 2.0 * PI * random_double();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let direction = uvw.transform(Vec3::new(r * phi.cos(), r * phi.sin(), z));
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+}
+
+fn point_sample(position: Point3, intensity: Color, p: Point3) -> Option<LightSample> {
+    let to_light = position - p;
+    let distance_squared = to_light.length_squared();
+    if distance_squared <= 0.0 {
+        return None;
+    }
+    Some(LightSample {
+        direction: to_light / distance_squared.sqrt(),
+        distance: distance_squared.sqrt(),
+        radiance: intensity / distance_squared,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    #[test]
+    fn point_light_falls_off_with_the_squared_distance() {
+        let light = PointLight::new(Point3::new(0.0, 2.0, 0.0), Color::new(8.0, 4.0, 2.0));
+        let sample = light.sample(Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert!((sample.direction - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((sample.distance - 2.0).abs() < 1e-12);
+        assert!((sample.radiance - Color::new(2.0, 1.0, 0.5)).length() < 1e-12);
+        // A point at the light itself has no direction towards it.
+        assert!(light.sample(Point3::new(0.0, 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn spot_light_fades_out_between_its_angles() {
+        let light = SpotLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            20.0,
+            40.0,
+        );
+        // Points one below the light, at the given angle from its axis.
+        let at = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let p = Point3::new(sin / cos, 0.0, 0.0);
+            light.sample(p).map(|sample| {
+                // Undo the falloff with distance to leave the falloff of the cone.
+                sample.radiance.x() * sample.distance * sample.distance
+            })
+        };
+        assert!((at(0.0).unwrap() - 1.0).abs() < 1e-12);
+        assert!((at(19.0).unwrap() - 1.0).abs() < 1e-12);
+        // Halfway between the angles, in cosine, the smoothstep is at a half.
+        let halfway = (0.5 * (20f64.to_radians().cos() + 40f64.to_radians().cos()))
+            .acos()
+            .to_degrees();
+        assert!((at(halfway).unwrap() - 0.5).abs() < 1e-9);
+        let fading = [25.0, 30.0, 35.0].map(|degrees| at(degrees).unwrap());
+        assert!(fading[0] > fading[1] && fading[1] > fading[2] && fading[2] > 0.0);
+        assert!(at(41.0).is_none());
+        // Points above the light are behind it.
+        assert!(light.sample(Point3::new(0.0, 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn directional_light_comes_from_within_its_cone() {
+        random::seed(1);
+        let direction = Vec3::new(1.0, -1.0, 0.0);
+        let irradiance = Color::new(3.0, 2.0, 1.0);
+        let sharp = DirectionalLight::new(direction, irradiance, 0.0);
+        let sample = sharp.sample(Point3::default()).unwrap();
+        assert!((sample.direction + direction.normalized()).length() < 1e-9);
+        assert_eq!(sample.distance, f64::INFINITY);
+        assert_eq!(sample.radiance, irradiance);
+
+        // With an angular diameter of 10 degrees, directions spread up to 5 degrees from the
+        // center of the sun, and cover the cone evenly.
+        let soft = DirectionalLight::new(direction, irradiance, 10.0);
+        let cos_half_angle = 5f64.to_radians().cos();
+        let n = 10_000;
+        let mut inner = 0;
+        for _ in 0..n {
+            let sample = soft.sample(Point3::default()).unwrap();
+            assert_eq!(sample.radiance, irradiance);
+            assert!((sample.direction.length() - 1.0).abs() < 1e-9);
+            let cosine = -sample.direction.dot(direction.normalized());
+            assert!(cosine >= cos_half_angle - 1e-12);
+            // The inner half of the cone, by solid angle.
+            if cosine > 0.5 * (1.0 + cos_half_angle) {
+                inner += 1;
+            }
+        }
+        let fraction = inner as f64 / n as f64;
+        assert!((fraction - 0.5).abs() < 0.025, "{fraction}");
+    }
+}
@@ -1,104 +1,229 @@
-mod camera;
-mod color;
-mod hittable;
-mod hittable_list;
-mod interval;
-mod material;
-mod ray;
-mod sphere;
-mod vec3;
-use camera::Camera;
-use core::f64;
-use material::*;
-use rand::{thread_rng, Rng};
-use std::sync::Arc;
-use vec3::{Color, Vec3};
-
-use crate::{hittable_list::HittableList, sphere::Sphere, vec3::Point3};
+use std::{path::PathBuf, process, sync::Arc};
+
+use clap::{Parser, ValueEnum};
+use ray_tracer::{
+    output, random, scene, scenes, Background, BvhNode, EnvironmentMap, Point3, Scene, Vec3,
+};
+
+/// Render a scene file or one of the built-in scenes to an image.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Scene file (TOML) to render.
+    #[arg(conflicts_with = "builtin")]
+    scene: Option<PathBuf>,
+
+    /// Built-in scene to render when no scene file is given.
+    #[arg(long, value_enum, default_value_t = Builtin::RandomSpheres)]
+    builtin: Builtin,
+
+    /// Output image. The format follows the extension: png, ppm (binary 8-bit) or pfm (32-bit
+    /// float).
+    #[arg(short, long, default_value = "output.png")]
+    output: PathBuf,
+
+    /// Ratio of image width over height.
+    #[arg(long)]
+    aspect_ratio: Option<f64>,
+
+    /// Rendered image width in pixels.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// Count of random samples for each pixel.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    samples: Option<u32>,
+
+    /// Maximum number of ray bounces into the scene.
+    #[arg(long)]
+    max_depth: Option<u32>,
+
+    /// Bounces every path makes before Russian roulette may end it. Set it to the maximum
+    /// depth to turn Russian roulette off.
+    #[arg(long)]
+    min_bounces: Option<u32>,
+
+    /// Vertical view angle (field of view) in degrees.
+    #[arg(long)]
+    vfov: Option<f64>,
+
+    /// Point the camera is looking from, as `x,y,z`.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    lookfrom: Option<Point3>,
+
+    /// Point the camera is looking at, as `x,y,z`.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    lookat: Option<Point3>,
+
+    /// Camera-relative "up" direction, as `x,y,z`.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    vup: Option<Vec3>,
+
+    /// Variation angle of rays through each pixel in degrees (the aperture). 0 disables depth of
+    /// field.
+    #[arg(long, alias = "aperture")]
+    defocus_angle: Option<f64>,
+
+    /// Distance from the camera to the plane of perfect focus.
+    #[arg(long)]
+    focus_dist: Option<f64>,
+
+    /// What rays escaping the scene see: `sky` for the daylight gradient, a solid color as
+    /// `r,g,b` (`0,0,0` leaves the scene lit by its emissive materials alone), or the path of
+    /// an equirectangular environment map such as a Radiance `.hdr` file.
+    #[arg(long, value_parser = parse_background)]
+    background: Option<Background>,
+
+    /// Time at which the shutter opens. Objects that move while it is open are blurred.
+    #[arg(long, allow_hyphen_values = true)]
+    shutter_open: Option<f64>,
+
+    /// Time at which the shutter closes. Equal to the opening time to freeze motion.
+    #[arg(long, allow_hyphen_values = true)]
+    shutter_close: Option<f64>,
+
+    /// Leave the lights to light sampling alone instead of combining it with material sampling
+    /// by multiple importance sampling, for comparison.
+    #[arg(long)]
+    no_mis: bool,
+
+    /// Trace wavelengths of light instead of RGB colors, so that dispersive glass splits light
+    /// into its colors.
+    #[arg(long)]
+    spectral: bool,
+
+    /// Number of render threads. Defaults to one per logical CPU.
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Seed for the random numbers, making renders (and the built-in scenes) reproducible.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Don't show a progress bar.
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Builtin {
+    /// The final scene of "Ray Tracing in One Weekend".
+    RandomSpheres,
+    /// The same scene with the small diffuse spheres bouncing, blurred by the camera shutter.
+    BouncingSpheres,
+    /// Two large spheres with a checker texture.
+    CheckeredSpheres,
+    /// Spheres with a Perlin noise marble texture.
+    PerlinSpheres,
+    /// Colored quads facing the camera.
+    Quads,
+    /// The marble spheres lit by a sphere and a quad light.
+    SimpleLight,
+    /// The Cornell box.
+    CornellBox,
+    /// The Cornell box with blocks of smoke and fog.
+    CornellSmoke,
+}
 
 fn main() {
-    // World
-    let mut world: HittableList = HittableList::new();
-
-    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat: f64 = thread_rng().gen();
-            let center = Point3::new(
-                a as f64 + 0.9 * thread_rng().gen::<f64>(),
-                0.2,
-                b as f64 + 0.9 * thread_rng().gen::<f64>(),
-            );
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::random() * Color::random();
-                    let sphere_material = Arc::new(Lambertian::new(albedo));
-                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random_in_range(0.5, 1.0);
-                    let fuzz: f64 = thread_rng().gen_range(0.0..0.5);
-                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
-                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
-                } else {
-                    // glass
-                    let sphere_material = Arc::new(Dielectric::new(1.5));
-                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
-                }
-            }
-        }
+    let args = Args::parse();
+
+    // Catch a bad output path before spending any time on the render.
+    output::OutputFormat::from_path(&args.output).unwrap_or_else(|e| fail(e));
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap_or_else(|e| fail(e));
+    }
+    if let Some(seed) = args.seed {
+        random::seed(seed);
+    }
+
+    let scene = match &args.scene {
+        Some(path) => scene::load_scene(path).unwrap_or_else(|e| fail(e)),
+        None => match args.builtin {
+            Builtin::RandomSpheres => scenes::random_spheres(),
+            Builtin::BouncingSpheres => scenes::bouncing_spheres(),
+            Builtin::CheckeredSpheres => scenes::checkered_spheres(),
+            Builtin::PerlinSpheres => scenes::perlin_spheres(),
+            Builtin::Quads => scenes::quads(),
+            Builtin::SimpleLight => scenes::simple_light(),
+            Builtin::CornellBox => scenes::cornell_box(),
+            Builtin::CornellSmoke => scenes::cornell_smoke(),
+        },
+    };
+    let Scene {
+        mut camera,
+        world,
+        lights,
+        analytic_lights,
+    } = scene;
+
+    // Command line settings take precedence over those of the scene.
+    let cam = &mut camera;
+    set(&mut cam.aspect_ratio, args.aspect_ratio);
+    set(&mut cam.image_width, args.width);
+    set(&mut cam.samples_per_pixel, args.samples);
+    set(&mut cam.max_depth, args.max_depth);
+    set(&mut cam.min_bounces, args.min_bounces);
+    set(&mut cam.vfov, args.vfov);
+    set(&mut cam.lookfrom, args.lookfrom);
+    set(&mut cam.lookat, args.lookat);
+    set(&mut cam.vup, args.vup);
+    set(&mut cam.defocus_angle, args.defocus_angle);
+    set(&mut cam.focus_dist, args.focus_dist);
+    set(&mut cam.background, args.background);
+    set(&mut cam.shutter_open, args.shutter_open);
+    set(&mut cam.shutter_close, args.shutter_close);
+    if args.no_mis {
+        cam.mis = false;
+    }
+    if args.spectral {
+        cam.spectral = true;
     }
+    cam.validate().unwrap_or_else(|e| fail(e));
+    cam.seed = args.seed;
+    cam.show_progress = !args.quiet;
+    cam.initialize();
+
+    let world = BvhNode::new(world);
+    let image = camera.render(&world, &lights, &analytic_lights);
+    output::write_image(&args.output, &image).unwrap_or_else(|e| fail(e));
+}
+
+fn set<T>(setting: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *setting = value;
+    }
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{e}"))?;
+    match values[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err("expected three comma separated numbers".to_string()),
+    }
+}
+
+fn parse_background(s: &str) -> Result<Background, String> {
+    if s == "sky" {
+        Ok(Background::Sky)
+    } else if let Ok(color) = parse_vec3(s) {
+        Ok(Background::Solid(color))
+    } else {
+        EnvironmentMap::open(s)
+            .map(|map| Background::Environment(Arc::new(map)))
+            .map_err(|e| format!("expected `sky`, r,g,b or an environment map: {e}"))
+    }
+}
 
-    let material1 = Arc::new(Dielectric::new(1.5));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
-    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
-    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
-
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width = 1200;
-    let samples_per_pixel = 500;
-    let max_depth = 50;
-    let vfov = 20.0;
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let defocus_angle = 0.6;
-    let focus_dist = 10.0;
-
-    let mut cam: Camera = Camera::new(
-        aspect_ratio,
-        image_width,
-        samples_per_pixel,
-        max_depth,
-        vfov,
-        lookfrom,
-        lookat,
-        vup,
-        defocus_angle,
-        focus_dist,
-    );
-
-    cam.render(&world);
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
 }
@@ -1,73 +1,316 @@
 use core::f64;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
+    color::luminance,
     hittable::HitRecord,
+    microfacet::{
+        dielectric_reflection, dielectric_transmission, fresnel_conductor, fresnel_dielectric,
+        fresnel_schlick, refract, Ggx,
+    },
+    onb::Onb,
+    pdf::{
+        CosinePdf, FuzzyReflectionPdf, GgxDielectricPdf, GgxReflectionPdf, Pdf, SpherePdf,
+        WeightedMixturePdf,
+    },
+    random::random_double,
     ray::Ray,
+    spectrum::{RefractiveIndex, REFERENCE_WAVELENGTH},
+    texture::{SolidColor, Texture},
+    thin_film::{ConductorFilm, DielectricFilm, ThinFilm},
     vec3::{Color, Vec3},
 };
 
+// How a material scatters an incoming ray.
+pub enum ScatterRecord {
+    // Along a single direction picked by the material, such as a mirror reflection. The
+    // attenuation already accounts for the choice of direction.
+    Specular { attenuation: Color, ray: Ray },
+    // Over a distribution of directions. The renderer samples it, possibly combined with
+    // directions towards the lights, and weighs each direction with `Scatter::eval`.
+    Pdf(Box<dyn Pdf>),
+}
+
 pub trait Scatter: Sync + Send {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    // Returns None when the ray is absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        // The BSDF times the cosine of the scattered direction to the normal, for materials
+        // with a `ScatterRecord::Pdf`.
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        // Materials don't emit light unless they say otherwise.
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        // For materials that rays can pass through, the absorption coefficient of the medium
+        // behind the surface, by which the renderer dims light travelling through it. None for
+        // materials that rays don't enter.
+        None
+    }
+
+    fn is_spectral(&self) -> bool {
+        // Whether the material depends on the wavelength of light in ways RGB colors can't
+        // capture, such as bending each wavelength its own way, so that a spectral path can
+        // only go on with its hero wavelength, which the material reads from the ray.
+        false
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
 }
 
 pub struct Dielectric {
     // Refractive index in vacuum or air, or the ratio of the material's refractive index over
     // the refractive index of the enclosing media
+    refraction_index: RefractiveIndex,
+    absorption: Color,
+    film: Option<DielectricFilm>,
+}
+
+// Glass with microfacet roughness, such as frosted or etched glass, which reflects and refracts
+// light by the exact Fresnel equations.
+pub struct RoughDielectric {
     refraction_index: f64,
+    ggx: Ggx,
+    absorption: Color,
 }
 
 pub struct Metal {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
     fuzz: f64,
+    film: Option<ConductorFilm>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Lambertian { albedo }
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Lambertian { tex }
     }
 }
 
 impl Scatter for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(CosinePdf::new(rec.normal))))
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let cos_theta = rec.normal.dot(scattered.direction().normalized());
+        if cos_theta <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
         }
-
-        let scattered = Ray::new(rec.p, scatter_direction);
-        let attenuation = self.albedo;
-        Some((attenuation, scattered))
+        self.tex.value(rec.u, rec.v, &rec.p) * (cos_theta / PI)
     }
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Metal { albedo, fuzz }
+        Self::from_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>, fuzz: f64) -> Self {
+        Metal {
+            tex,
+            fuzz,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(tex: Arc<dyn Texture>, fuzz: f64, film: ThinFilm) -> Self {
+        // A metal under a thin coating, such as oxidized or oil stained steel, whose reflections
+        // take on interference colors.
+        Metal {
+            tex,
+            fuzz,
+            film: Some(ConductorFilm::new(film)),
+        }
+    }
+
+    fn reflectance(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let albedo = self.tex.value(rec.u, rec.v, &rec.p);
+        match &self.film {
+            Some(film) => {
+                let cos_theta = -r_in.direction().normalized().dot(rec.normal);
+                film.reflectance(cos_theta.clamp(0.0, 1.0), albedo, r_in.wavelength())
+            }
+            None => albedo,
+        }
     }
 }
 
 impl Scatter for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let reflected: Vec3 = r_in.direction().reflect(rec.normal);
-        let reflected = reflected.normalized() + (self.fuzz * Vec3::random_unit_vector());
-        let scattered = Ray::new(rec.p, reflected);
-        let attenuation = self.albedo;
-        if scattered.direction().dot(rec.normal) > 0.0 {
-            Some((attenuation, scattered))
-        } else {
-            None
+        if self.fuzz <= 0.0 {
+            // A perfect mirror only ever reflects along one direction.
+            let attenuation = self.reflectance(r_in, rec);
+            return Some(ScatterRecord::Specular {
+                attenuation,
+                ray: Ray::with_time(rec.p, reflected, r_in.time()),
+            });
+        }
+        Some(ScatterRecord::Pdf(Box::new(FuzzyReflectionPdf::new(
+            reflected, self.fuzz,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        // Directions are weighted by their density, so that sampling the fuzz leaves just the
+        // albedo. Those below the surface are absorbed.
+        if scattered.direction().dot(rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let reflected = r_in.direction().reflect(rec.normal);
+        let pdf = FuzzyReflectionPdf::new(reflected, self.fuzz);
+        self.reflectance(r_in, rec) * pdf.value(scattered.direction())
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.film.is_some()
+    }
+}
+
+// A metal with microfacet roughness, reflecting light by the Fresnel equations for its complex
+// refractive index. Unlike `Metal`, its color comes from measured optical constants, and it
+// conserves energy at every roughness.
+pub struct Conductor {
+    // The real and imaginary parts of the refractive index, for each color channel.
+    eta: Color,
+    k: Color,
+    ggx: Ggx,
+}
+
+// Measured refractive indices of common metals.
+#[derive(Clone, Copy, Debug)]
+pub enum MetalPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl MetalPreset {
+    pub fn ior(self) -> (Color, Color) {
+        // Returns (eta, k) at red, green and blue wavelengths.
+        match self {
+            MetalPreset::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            MetalPreset::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            MetalPreset::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            MetalPreset::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self::with_anisotropy(eta, k, roughness, 0.0)
+    }
+
+    pub fn from_preset(preset: MetalPreset, roughness: f64) -> Self {
+        let (eta, k) = preset.ior();
+        Self::new(eta, k, roughness)
+    }
+
+    pub fn with_anisotropy(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Self {
+        // Roughness is in [0, 1], and anisotropy in [0, 1) stretches highlights along the
+        // tangent of the surface's orthonormal basis.
+        Conductor {
+            eta,
+            k,
+            ggx: Ggx::from_roughness(roughness, anisotropy),
         }
     }
 }
 
+impl Scatter for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let unit_direction = r_in.direction().normalized();
+        if self.ggx.is_smooth() {
+            let cos_theta = rec.normal.dot(-unit_direction);
+            return Some(ScatterRecord::Specular {
+                attenuation: fresnel_conductor(cos_theta, self.eta, self.k),
+                ray: Ray::with_time(rec.p, unit_direction.reflect(rec.normal), r_in.time()),
+            });
+        }
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_basis(-unit_direction);
+        Some(ScatterRecord::Pdf(Box::new(GgxReflectionPdf::new(
+            uvw, wo, self.ggx,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        // The Cook-Torrance BRDF, F D G / (4 cos_o cos_i), times cos_i.
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_basis(-r_in.direction().normalized());
+        let wi = uvw.to_basis(scattered.direction().normalized());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let h = (wo + wi).normalized();
+        let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+        fresnel * (self.ggx.d(h) * self.ggx.g(wo, wi) / (4.0 * wo.z()))
+    }
+}
+
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Dielectric { refraction_index }
+        Self::with_absorption(refraction_index, Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn with_absorption(refraction_index: f64, absorption: Color) -> Self {
+        // The absorption coefficient is per unit of distance travelled inside, following the
+        // Beer-Lambert law, so thick glass is tinted more deeply than thin glass.
+        Self::with_dispersion(RefractiveIndex::Constant(refraction_index), absorption)
+    }
+
+    pub fn with_dispersion(refraction_index: RefractiveIndex, absorption: Color) -> Self {
+        // Glass whose refractive index depends on the wavelength, which splits light into its
+        // colors in spectral renders. RGB renders use the index at the reference wavelength.
+        Dielectric {
+            refraction_index,
+            absorption,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(
+        refraction_index: RefractiveIndex,
+        absorption: Color,
+        film: ThinFilm,
+    ) -> Self {
+        // Glass under a thin coating, whose reflections take on interference colors. With a
+        // refractive index of 1, it is the film alone, like a soap bubble.
+        Dielectric {
+            film: Some(DielectricFilm::new(film, refraction_index)),
+            ..Self::with_dispersion(refraction_index, absorption)
+        }
+    }
+
+    pub fn from_color(refraction_index: f64, color: Color, distance: f64) -> Self {
+        // Glass that light comes out of with the given color after travelling the given
+        // distance through it.
+        Self::with_absorption(refraction_index, absorption_from_color(color, distance))
     }
 
     fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
@@ -79,12 +322,15 @@ impl Dielectric {
 }
 
 impl Scatter for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let refraction_index = self
+            .refraction_index
+            .at(r_in.wavelength().unwrap_or(REFERENCE_WAVELENGTH));
         let ri: f64 = if rec.front_face {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_direction: Vec3 = r_in.direction().normalized();
@@ -92,16 +338,506 @@ impl Scatter for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract: bool = ri * sin_theta > 1.0;
-        let direction: Vec3;
+        let (reflects, attenuation) = match &self.film {
+            _ if cannot_refract => (true, white),
+            None => (
+                Dielectric::reflectance(cos_theta, ri) > random_double(),
+                white,
+            ),
+            Some(film) => {
+                // The film is on the outside, so light from inside meets it at the angle it
+                // would leave at. Reflection is picked by the average reflectance, and the
+                // attenuation makes up for the color.
+                let cos_outside = if rec.front_face {
+                    cos_theta
+                } else {
+                    (1.0 - (ri * sin_theta).powi(2)).sqrt()
+                };
+                let reflectance = film.reflectance(cos_outside, r_in.wavelength());
+                let p = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+                if random_double() < p {
+                    (true, reflectance / p)
+                } else {
+                    (false, (white - reflectance) / (1.0 - p))
+                }
+            }
+        };
+        let direction: Vec3 = if reflects {
+            Vec3::reflect(unit_direction, rec.normal)
+        } else {
+            Vec3::refract(&unit_direction, &rec.normal, ri)
+        };
+
+        let scattered = Ray::with_time(rec.p, direction, r_in.time());
+
+        Some(ScatterRecord::Specular {
+            attenuation,
+            ray: scattered,
+        })
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        Some(self.absorption)
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.refraction_index.is_dispersive() || self.film.is_some()
+    }
+}
+
+pub fn absorption_from_color(color: Color, distance: f64) -> Color {
+    // The absorption coefficient that turns white light into the given color over the given
+    // distance. Black channels absorb everything.
+    let coefficient = |c: f64| {
+        if c <= 0.0 {
+            f64::INFINITY
+        } else {
+            -c.min(1.0).ln() / distance
+        }
+    };
+    Color::new(
+        coefficient(color.x()),
+        coefficient(color.y()),
+        coefficient(color.z()),
+    )
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> Self {
+        // Roughness is in [0, 1], where 0 is perfectly smooth glass.
+        Self::with_absorption(refraction_index, roughness, Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn with_absorption(refraction_index: f64, roughness: f64, absorption: Color) -> Self {
+        RoughDielectric {
+            refraction_index,
+            ggx: Ggx::from_roughness(roughness, 0.0),
+            absorption,
+        }
+    }
+
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        // The refractive index beyond the surface over the one on the ray's side.
+        if rec.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        }
+    }
+}
 
-        if cannot_refract || Dielectric::reflectance(cos_theta, ri) > rand::random() {
-            direction = Vec3::reflect(unit_direction, rec.normal);
+impl Scatter for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let wo = -r_in.direction().normalized();
+        let eta = self.eta(rec);
+        if self.ggx.is_smooth() {
+            // Reflect or refract in proportion to the reflectance, dividing radiance by eta
+            // squared as it crosses into the other medium like the rough case does.
+            let reflectance = fresnel_dielectric(wo.dot(rec.normal), eta);
+            let refracted = refract(wo, rec.normal, eta);
+            let (direction, attenuation) = match refracted {
+                Some(direction) if random_double() >= reflectance => (direction, 1.0 / (eta * eta)),
+                _ => ((-wo).reflect(rec.normal), 1.0),
+            };
+            return Some(ScatterRecord::Specular {
+                attenuation: Color::new(attenuation, attenuation, attenuation),
+                ray: Ray::with_time(rec.p, direction, r_in.time()),
+            });
+        }
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_basis(wo);
+        Some(ScatterRecord::Pdf(Box::new(GgxDielectricPdf::new(
+            uvw, wo, self.ggx, eta,
+        ))))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_basis(-r_in.direction().normalized());
+        let wi = uvw.to_basis(scattered.direction().normalized());
+        let eta = self.eta(rec);
+        let f = dielectric_reflection(&self.ggx, wo, wi, eta)
+            + dielectric_transmission(&self.ggx, wo, wi, eta);
+        Color::new(f, f, f)
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        Some(self.absorption)
+    }
+}
+
+// A principled material in the style of Disney's and Blender's, which covers plastics, metals,
+// coated paints, cloth and rough glass with one set of parameters. Every parameter can be driven
+// by a texture; scalar ones, between 0 and 1, use the luminance of their texture.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    // 0 for dielectrics, 1 for metals, whose reflections are tinted by the base color.
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Scales the reflectance of dielectrics, with 0.5 giving the exact one for `ior`.
+    pub specular: Arc<dyn Texture>,
+    // A colorless coat on top of the rest, like the varnish of car paint, with its own
+    // roughness.
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: f64,
+    // A soft white sheen at grazing angles, like that of cloth.
+    pub sheen: Arc<dyn Texture>,
+    // The fraction of dielectric light refracted through the surface rather than diffusely
+    // reflected, 1 for glass. Refracted light is tinted by the base color.
+    pub transmission: Arc<dyn Texture>,
+    pub ior: f64,
+    // Light emitted from the front face.
+    pub emission: Arc<dyn Texture>,
+}
+
+// The parameters of a principled material at one point of a surface.
+struct PrincipledParameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    clearcoat: f64,
+    sheen: f64,
+    transmission: f64,
+}
+
+impl PrincipledParameters {
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn glass_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn from_texture(base_color: Arc<dyn Texture>) -> Self {
+        // A rough plastic, until the other parameters are set.
+        Principled {
+            base_color,
+            metallic: gray(0.0),
+            roughness: gray(0.5),
+            specular: gray(0.5),
+            clearcoat: gray(0.0),
+            clearcoat_roughness: 0.1,
+            sheen: gray(0.0),
+            transmission: gray(0.0),
+            ior: 1.5,
+            emission: gray(0.0),
+        }
+    }
+
+    fn parameters(&self, rec: &HitRecord) -> PrincipledParameters {
+        let scalar =
+            |tex: &Arc<dyn Texture>| luminance(tex.value(rec.u, rec.v, &rec.p)).clamp(0.0, 1.0);
+        PrincipledParameters {
+            base_color: self.base_color.value(rec.u, rec.v, &rec.p),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            clearcoat: scalar(&self.clearcoat),
+            sheen: scalar(&self.sheen),
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        // The refractive index beyond the surface over the one on the ray's side.
+        if rec.front_face {
+            self.ior
         } else {
-            direction = Vec3::refract(&unit_direction, &rec.normal, ri);
+            1.0 / self.ior
+        }
+    }
+}
+
+impl Scatter for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // Each lobe is sampled in rough proportion to the light it reflects towards the viewer.
+        let params = self.parameters(rec);
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_basis(-r_in.direction().normalized());
+        let eta = self.eta(rec);
+        let ggx = Ggx::from_roughness(params.roughness, 0.0);
+        let reflectance = fresnel_dielectric(wo.z(), eta);
+
+        let mut pdf = WeightedMixturePdf::new();
+        pdf.add(
+            params.diffuse_weight() * (1.0 - reflectance),
+            Box::new(CosinePdf::new(rec.normal)),
+        );
+        pdf.add(
+            params.metallic
+                + params.diffuse_weight() * (2.0 * params.specular * reflectance).clamp(0.1, 1.0),
+            Box::new(GgxReflectionPdf::new(uvw, wo, ggx)),
+        );
+        pdf.add(
+            params.glass_weight(),
+            Box::new(GgxDielectricPdf::new(Onb::new(rec.normal), wo, ggx, eta)),
+        );
+        pdf.add(
+            params.clearcoat * 0.1,
+            Box::new(GgxReflectionPdf::new(
+                Onb::new(rec.normal),
+                wo,
+                Ggx::from_roughness(self.clearcoat_roughness, 0.0),
+            )),
+        );
+        Some(ScatterRecord::Pdf(Box::new(pdf)))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let params = self.parameters(rec);
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_basis(-r_in.direction().normalized());
+        let wi = uvw.to_basis(scattered.direction().normalized());
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let eta = self.eta(rec);
+        let ggx = Ggx::from_roughness(params.roughness, 0.0);
+
+        if wi.z() < 0.0 {
+            // Refraction through the rough interface.
+            let transmission = params.glass_weight() * dielectric_transmission(&ggx, wo, wi, eta);
+            return transmission * params.base_color;
         }
 
-        let scattered = Ray::new(rec.p, direction);
+        let h = (wo + wi).normalized();
+        let cos_d = wi.dot(h);
+
+        // Disney's diffuse, which darkens smooth surfaces and brightens rough ones at grazing
+        // angles, plus the sheen.
+        let schlick_weight = |cos: f64| (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+        let fd90 = 0.5 + 2.0 * params.roughness * cos_d * cos_d;
+        let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+        let sheen = params.sheen * schlick_weight(cos_d);
+        let mut f = params.diffuse_weight()
+            * (params.base_color * (retro / PI) + Color::new(sheen, sheen, sheen));
+
+        // Specular reflections of the dielectric, the glass and the metal share one lobe.
+        let reflectance = fresnel_dielectric(wo.dot(h), eta);
+        let dielectric = params.diffuse_weight() * (2.0 * params.specular * reflectance).min(1.0)
+            + params.glass_weight() * reflectance;
+        let specular = Color::new(dielectric, dielectric, dielectric)
+            + params.metallic * fresnel_schlick(wo.dot(h), params.base_color);
+        f += specular * (ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z() * wi.z()));
+
+        if params.clearcoat > 0.0 {
+            let coat = Ggx::from_roughness(self.clearcoat_roughness, 0.0);
+            let coat_reflectance = params.clearcoat * fresnel_dielectric(wo.dot(h), 1.5);
+            f += Color::new(1.0, 1.0, 1.0)
+                * (coat_reflectance * coat.d(h) * coat.g(wo, wi) / (4.0 * wo.z() * wi.z()));
+        }
+
+        f * wi.z()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        // Refracted light is tinted at the surface instead.
+        Some(Color::new(0.0, 0.0, 0.0))
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emission.value(rec.u, rec.v, &rec.p)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+}
 
-        Some((attenuation, scattered))
+fn gray(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+pub struct DiffuseLight {
+    tex: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        DiffuseLight { tex }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        // Lights only emit from their front face, so that the back of a light is dark.
+        if rec.front_face {
+            self.tex.value(rec.u, rec.v, &rec.p)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+}
+
+// The phase function of a participating medium, scattering light equally in all directions.
+pub struct Isotropic {
+    tex: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Isotropic { tex }
+    }
+}
+
+impl Scatter for Isotropic {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(SpherePdf)))
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, _scattered: &Ray) -> Color {
+        // There is no surface, hence no cosine term.
+        self.tex.value(rec.u, rec.v, &rec.p) / (4.0 * PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    // Estimates how much of the light arriving from all directions a material sends towards
+    // wo, once by sampling its own density and once uniformly over the sphere. The normal is +z
+    // and on the side of wo; `front_face` says which side of the material that is.
+    fn reflected(material: &dyn Scatter, wo: Vec3, front_face: bool) -> (f64, f64) {
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            front_face,
+            ..HitRecord::default()
+        };
+        let r_in = Ray::new(wo, -wo);
+        let Some(ScatterRecord::Pdf(pdf)) = material.scatter(&r_in, &rec) else {
+            panic!("the material should scatter over a density");
+        };
+        let eval = |direction: Vec3| material.eval(&r_in, &rec, &Ray::new(rec.p, direction)).x();
+
+        let n = 200_000;
+        let sampled = (0..n)
+            .map(|_| {
+                let direction = pdf.generate();
+                let density = pdf.value(direction);
+                if density > 0.0 {
+                    eval(direction) / density
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f64>()
+            / n as f64;
+        let uniform = (0..n)
+            .map(|_| eval(Vec3::random_unit_vector()) * 4.0 * PI)
+            .sum::<f64>()
+            / n as f64;
+        (sampled, uniform)
+    }
+
+    #[test]
+    fn principled_sampling_covers_every_lobe() {
+        // Any direction the material scatters light into must be sampled, or the renderer
+        // would miss that light.
+        random::seed(5);
+        let mut metal = Principled::new(Color::new(0.9, 0.9, 0.9));
+        metal.metallic = gray(1.0);
+        metal.roughness = gray(0.6);
+        let mut glass = Principled::new(Color::new(1.0, 1.0, 1.0));
+        glass.transmission = gray(1.0);
+        glass.roughness = gray(0.5);
+        let mut coated = Principled::new(Color::new(0.2, 0.4, 0.8));
+        coated.clearcoat = gray(1.0);
+        coated.clearcoat_roughness = 0.5;
+        coated.sheen = gray(0.5);
+        let plastic = Principled::new(Color::new(0.8, 0.2, 0.2));
+
+        let wo = Vec3::new(0.4, 0.1, 1.0).normalized();
+        for (name, material, front_face) in [
+            ("plastic", &plastic, true),
+            ("metal", &metal, true),
+            ("glass from outside", &glass, true),
+            ("glass from inside", &glass, false),
+            ("coated", &coated, true),
+        ] {
+            let (sampled, uniform) = reflected(material, wo, front_face);
+            assert!(
+                (sampled - uniform).abs() < 0.05 * uniform,
+                "{name}: {sampled} sampled, {uniform} uniformly"
+            );
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_sampling_covers_both_sides() {
+        random::seed(7);
+        let glass = RoughDielectric::new(1.5, 0.5);
+        for wo in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.3, 0.4).normalized(),
+        ] {
+            for front_face in [true, false] {
+                let (sampled, uniform) = reflected(&glass, wo, front_face);
+                assert!(
+                    (sampled - uniform).abs() < 0.05 * uniform,
+                    "{sampled} sampled, {uniform} uniformly"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn absorption_from_color_round_trips() {
+        let color = Color::new(0.9, 0.5, 0.0);
+        let absorption = absorption_from_color(color, 2.0);
+        // White light going 2 units through the glass comes out in the color.
+        for i in 0..3 {
+            assert!(((-absorption[i] * 2.0).exp() - color[i]).abs() < 1e-12);
+        }
+        assert_eq!(absorption.z(), f64::INFINITY);
+        // Colors brighter than white can't be reached by absorbing light, and stay clear.
+        assert_eq!(
+            absorption_from_color(Color::new(1.5, 1.0, 1.0), 1.0),
+            Color::default()
+        );
+    }
+
+    #[test]
+    fn principled_metal_conserves_energy() {
+        // A white metal reflects everything, less what the microfacets hide from each other,
+        // which grows with the roughness.
+        random::seed(6);
+        let mut metal = Principled::new(Color::new(1.0, 1.0, 1.0));
+        metal.metallic = gray(1.0);
+        for (roughness, least) in [(0.2, 0.95), (0.5, 0.8), (0.9, 0.3)] {
+            metal.roughness = gray(roughness);
+            for wo in [
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(1.0, 0.0, 0.5).normalized(),
+            ] {
+                let (albedo, _) = reflected(&metal, wo, true);
+                assert!(
+                    albedo > least && albedo < 1.01,
+                    "{albedo} at roughness {roughness}"
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,253 @@
+use std::ops::Mul;
+
+use crate::vec3::{Point3, Vec3};
+
+// A 4x4 matrix in row-major order, used for affine transforms of points and vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        m: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Mat4 { m }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut t = Mat4::IDENTITY;
+        for i in 0..3 {
+            t.m[i][3] = offset[i];
+        }
+        t
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut s = Mat4::IDENTITY;
+        for i in 0..3 {
+            s.m[i][i] = factors[i];
+        }
+        s
+    }
+
+    // Counter-clockwise rotations, in degrees, when looking down the axis towards the origin.
+    pub fn rotation_x(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_y(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Mat4::new([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_z(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Mat4::new([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = Mat4::IDENTITY;
+        for (i, row) in self.m.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                t.m[j][i] = value;
+            }
+        }
+        t
+    }
+
+    pub fn linear_determinant(&self) -> f64 {
+        // The determinant of the upper-left 3x3 part, i.e. without the translation. Its
+        // magnitude is the factor the matrix scales volumes by.
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn inverse(&self) -> Option<Self> {
+        // Gauss-Jordan elimination with partial pivoting. Returns None for singular matrices,
+        // such as a scaling by zero.
+        if !self.m.iter().flatten().all(|v| v.is_finite()) {
+            return None;
+        }
+        let mut a = self.m;
+        let mut inv = Mat4::IDENTITY.m;
+        // Pivots are compared against the largest entry of the upper left 3x3 block, so that
+        // matrices aren't rejected for their units alone. The translation column is left out,
+        // as a far away object is no harder to invert than one at the origin.
+        let largest = self.m[..3]
+            .iter()
+            .flat_map(|row| &row[..3])
+            .fold(0.0, |max: f64, v| max.max(v.abs()));
+        let epsilon = 1e-12 * largest;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() <= epsilon {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for k in 0..4 {
+                a[col][k] *= scale;
+                inv[col][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[col][k];
+                    inv[row][k] -= factor * inv[col][k];
+                }
+            }
+        }
+
+        Some(Mat4::new(inv))
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        // Points are affected by the translation part of the matrix.
+        let m = &self.m;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        // Directions ignore the translation part of the matrix.
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    // The product applies `rhs` first, then `self`.
+    fn mul(self, rhs: Self) -> Self {
+        let mut product = Mat4::new([[0.0; 4]; 4]);
+        for i in 0..4 {
+            for j in 0..4 {
+                product.m[i][j] = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Mat4, b: Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!(
+                    (a.m[i][j] - b.m[i][j]).abs() < 1e-9,
+                    "{a:?} differs from {b:?} at ({i}, {j})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let m = Mat4::translation(Vec3::new(3.0, -2.0, 150.0))
+            * Mat4::rotation_z(30.0)
+            * Mat4::rotation_y(-45.0)
+            * Mat4::rotation_x(10.0)
+            * Mat4::scaling(Vec3::new(2.0, 0.5, 4.0));
+        let inverse = m.inverse().unwrap();
+        assert_near(m * inverse, Mat4::IDENTITY);
+        assert_near(inverse * m, Mat4::IDENTITY);
+
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let q = inverse.transform_point(m.transform_point(p));
+        assert!((q - p).length() < 1e-9);
+    }
+
+    #[test]
+    fn inverse_of_tiny_scale_is_relative_to_the_matrix() {
+        // A matrix whose entries are all tiny is as invertible as one whose entries are all 1.
+        let m = Mat4::new([
+            [1e-13, 0.0, 0.0, 0.0],
+            [0.0, 1e-13, 0.0, 0.0],
+            [0.0, 0.0, 1e-13, 0.0],
+            [0.0, 0.0, 0.0, 1e-13],
+        ]);
+        assert_near(m.inverse().unwrap() * m, Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn inverse_ignores_the_size_of_the_translation() {
+        let translation = Vec3::new(1e10, -1e10, 1e10);
+        let m = Mat4::translation(translation) * Mat4::scaling(Vec3::new(1e-3, 1e-3, 1e-3));
+        let expected = Mat4::scaling(Vec3::new(1e3, 1e3, 1e3)) * Mat4::translation(-translation);
+        let inverse = m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                let (a, b) = (inverse.m[i][j], expected.m[i][j]);
+                assert!((a - b).abs() <= 1e-9 * b.abs().max(1.0), "{inverse:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert_eq!(Mat4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+        // A single axis that is tiny next to the others flattens the object.
+        assert_eq!(
+            (Mat4::translation(Vec3::new(1.0, 2.0, 3.0))
+                * Mat4::scaling(Vec3::new(1.0, 1e-13, 1.0)))
+            .inverse(),
+            None
+        );
+        let mut m = Mat4::IDENTITY;
+        m.m[1] = m.m[0];
+        assert_eq!(m.inverse(), None);
+        assert_eq!(Mat4::scaling(Vec3::new(1.0, f64::NAN, 1.0)).inverse(), None);
+    }
+}
@@ -0,0 +1,388 @@
+use std::f64::consts::PI;
+
+use crate::{
+    random::random_double,
+    vec3::{Color, Vec3},
+};
+
+// The GGX (Trowbridge-Reitz) distribution of microfacet normals, for rough surfaces. Directions
+// are in a local frame where the surface normal is +z, with the x and y axes along the tangents
+// the roughness may stretch along.
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        // Very small widths make the distribution numerically unstable. Surfaces that smooth
+        // should be treated as perfect mirrors instead, see `is_smooth`.
+        Ggx {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        // A perceptually linear roughness in [0, 1], squared into the width of the distribution.
+        // An anisotropy in [0, 1) stretches the highlights along the x axis.
+        let alpha = roughness * roughness;
+        let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+        Self::new(alpha / aspect, alpha * aspect)
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, h: Vec3) -> f64 {
+        // The density of microfacet normals, with respect to projected solid angle.
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let t = (h.x() / self.alpha_x).powi(2) + (h.y() / self.alpha_y).powi(2) + h.z().powi(2);
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return f64::INFINITY;
+        }
+        let t = ((self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2)) / w.z().powi(2);
+        ((1.0 + t).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        // The fraction of microfacets seen from a direction that aren't hidden by others.
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        // The fraction of microfacets visible from both directions, taking into account that
+        // facets hidden from one are more likely to be hidden from the other.
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    pub fn visible_normal_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        // The density of `sample_visible_normal` returning h, with respect to solid angle.
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z()
+    }
+
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Samples the normals of the microfacets seen from wo in proportion to their projected
+        // area, following Heitz, "Sampling the GGX Distribution of Visible Normals" (2018).
+        // Stretch the view into the frame where the distribution is a hemisphere.
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).normalized();
+        let len_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len_squared > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / len_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        // Sample a point on the projected hemisphere, squashing half of the disk.
+        let r = random_double().sqrt();
+        let phi = 2.0 * PI * random_double();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // Unstretch back to the frame of the surface.
+        Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(0.0),
+        )
+        .normalized()
+    }
+}
+
+pub fn fresnel_conductor(cos_theta: f64, eta: Color, k: Color) -> Color {
+    // The exact reflectance of a metal with the complex refractive index eta + ik, averaged over
+    // both polarizations, for each color channel.
+    let mut reflectance = Color::default();
+    for i in 0..3 {
+        reflectance[i] = fresnel_conductor_channel(cos_theta, eta[i], k[i]);
+    }
+    reflectance
+}
+
+fn fresnel_conductor_channel(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    // The exact reflectance of an interface between dielectrics, averaged over both
+    // polarizations. eta is the refractive index on the far side over the one on the near side,
+    // where the near side is the one cos_theta is positive on.
+    let (mut cos_i, mut eta) = (cos_theta.clamp(-1.0, 1.0), eta);
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+    }
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+pub fn fresnel_schlick(cos_theta: f64, f0: Color) -> Color {
+    // Schlick's approximation of the reflectance, from the reflectance at normal incidence.
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+pub fn reflect(wo: Vec3, n: Vec3) -> Vec3 {
+    // Mirrors wo, pointing away from the surface, about the normal n.
+    2.0 * wo.dot(n) * n - wo
+}
+
+pub fn refract(wo: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    // Bends wo, pointing away from the surface on the side of n, through an interface whose
+    // refractive index on the far side over the near side is eta. Returns None on total
+    // internal reflection.
+    let cos_i = wo.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * n)
+}
+
+pub fn dielectric_reflection(ggx: &Ggx, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+    // The microfacet BRDF of a rough interface between dielectrics, times the cosine of wi.
+    // Both directions point away from the surface on the side of +z, and eta is the refractive
+    // index beyond the surface over the one on their side.
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return 0.0;
+    }
+    let h = (wo + wi).normalized();
+    fresnel_dielectric(wo.dot(h), eta) * ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z())
+}
+
+pub fn dielectric_transmission(ggx: &Ggx, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+    // The microfacet BTDF of a rough interface between dielectrics, times the cosine of wi, as
+    // in Walter et al., "Microfacet Models for Refraction through Rough Surfaces" (2007). wi
+    // points away from the surface on the far side.
+    if wo.z() <= 0.0 || wi.z() >= 0.0 {
+        return 0.0;
+    }
+    // The microfacet normal that refracts wo into wi.
+    let mut h = (eta * wi + wo).normalized();
+    if h.z() < 0.0 {
+        h = -h;
+    }
+    if h.dot(wi) >= 0.0 || h.dot(wo) <= 0.0 {
+        return 0.0;
+    }
+    let denominator = (wi.dot(h) + wo.dot(h) / eta).powi(2);
+    let transmittance = 1.0 - fresnel_dielectric(wo.dot(h), eta);
+    // Radiance is compressed into a smaller solid angle when entering a denser medium, which
+    // the division by eta squared accounts for.
+    transmittance * ggx.d(h) * ggx.g(wo, wi) * (wi.dot(h) * wo.dot(h)).abs()
+        / (wo.z() * denominator)
+        / (eta * eta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Integrates a function over the sphere of directions with the midpoint rule, on a grid even
+    // in the polar angle and the angle around the z axis.
+    fn integrate(f: impl Fn(Vec3) -> f64) -> f64 {
+        const STEPS: usize = 1000;
+        let d_theta = PI / STEPS as f64;
+        let d_phi = 2.0 * PI / STEPS as f64;
+        let mut sum = 0.0;
+        for i in 0..STEPS {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..STEPS {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let w = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(w) * theta.sin();
+            }
+        }
+        sum * d_theta * d_phi
+    }
+
+    fn distributions() -> [Ggx; 3] {
+        [
+            Ggx::from_roughness(0.5, 0.0),
+            Ggx::from_roughness(0.8, 0.0),
+            Ggx::from_roughness(0.6, 0.7),
+        ]
+    }
+
+    #[test]
+    fn normals_cover_the_surface() {
+        // The microfacets projected onto the surface add up to its area.
+        for ggx in distributions() {
+            let area = integrate(|h| ggx.d(h) * h.z());
+            assert!((area - 1.0).abs() < 1e-3, "{area}");
+        }
+    }
+
+    #[test]
+    fn white_furnace() {
+        // A mirror microfacet surface that only loses light to masking reflects all of the
+        // light the visible microfacets catch, wherever it goes.
+        for ggx in distributions() {
+            for wo in [
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.5, 0.3, 1.0).normalized(),
+                Vec3::new(1.0, -0.4, 0.3).normalized(),
+            ] {
+                let reflected = integrate(|wi| {
+                    let h = (wo + wi).normalized();
+                    ggx.d(h) * ggx.g1(wo) / (4.0 * wo.z())
+                });
+                assert!((reflected - 1.0).abs() < 2e-3, "{reflected} for {wo:?}");
+
+                // With masking of the reflections as well, some light is lost, but never
+                // gained.
+                let lit = integrate(|wi| {
+                    if wi.z() <= 0.0 {
+                        return 0.0;
+                    }
+                    let h = (wo + wi).normalized();
+                    ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z())
+                });
+                assert!(lit > 0.5 && lit <= 1.0 + 1e-3, "{lit} for {wo:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectrics_conserve_energy() {
+        // Light is reflected or refracted, and only lost to masking, which takes the most from
+        // rough surfaces seen from inside, where much of the light is reflected back. Refracted
+        // radiance is divided by eta squared, which the integral undoes to count the energy.
+        for ggx in distributions() {
+            for eta in [1.5, 1.0 / 1.5] {
+                for wo in [
+                    Vec3::new(0.0, 0.0, 1.0),
+                    Vec3::new(0.5, 0.3, 1.0).normalized(),
+                ] {
+                    let total = integrate(|wi| {
+                        dielectric_reflection(&ggx, wo, wi, eta)
+                            + eta * eta * dielectric_transmission(&ggx, wo, wi, eta)
+                    });
+                    assert!(total > 0.6 && total < 1.0 + 2e-3, "{total} for eta {eta}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_reflectance() {
+        for eta in [1.5f64, 2.4] {
+            // At normal incidence, ((eta - 1) / (eta + 1))^2, from either side.
+            let r0 = ((eta - 1.0) / (eta + 1.0)).powi(2);
+            assert!((fresnel_dielectric(1.0, eta) - r0).abs() < 1e-12);
+            assert!((fresnel_dielectric(-1.0, eta) - r0).abs() < 1e-12);
+
+            for cos_i in [0.9, 0.5, 0.1] {
+                // The Fresnel equations for both polarizations.
+                let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (eta * eta)).sqrt();
+                let rs = ((cos_i - eta * cos_t) / (cos_i + eta * cos_t)).powi(2);
+                let rp = ((eta * cos_i - cos_t) / (eta * cos_i + cos_t)).powi(2);
+                let reflectance = fresnel_dielectric(cos_i, eta);
+                assert!(
+                    (reflectance - 0.5 * (rs + rp)).abs() < 1e-9,
+                    "{reflectance}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn total_internal_reflection() {
+        // From inside glass, light beyond the critical angle is reflected entirely.
+        let eta = 1.5f64;
+        let cos_critical = (1.0 - 1.0 / (eta * eta)).sqrt();
+        for cos_i in [cos_critical - 1e-6, 0.5, 0.1, 0.0] {
+            assert_eq!(fresnel_dielectric(-cos_i, eta), 1.0);
+            assert_eq!(fresnel_dielectric(cos_i, 1.0 / eta), 1.0);
+        }
+        let inside = fresnel_dielectric(-(cos_critical + 0.01), eta);
+        assert!(inside < 1.0 && inside > fresnel_dielectric(-1.0, eta));
+        assert!(refract(
+            Vec3::new(0.8, 0.0, 0.6),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0 / eta
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn conductors_without_absorption_reflect_like_dielectrics() {
+        for eta in [1.5, 2.4] {
+            for cos_theta in [1.0, 0.9, 0.5, 0.1, 0.0] {
+                let conductor = fresnel_conductor_channel(cos_theta, eta, 0.0);
+                let dielectric = fresnel_dielectric(cos_theta, eta);
+                assert!(
+                    (conductor - dielectric).abs() < 1e-9,
+                    "{conductor} != {dielectric}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let wi = refract(wo, n, 1.5).unwrap();
+        assert!((wi.length() - 1.0).abs() < 1e-12);
+        // The sines of the angles are in the ratio of the refractive indices, and the refracted
+        // ray goes on through the surface, away from the viewer.
+        assert!((wi.x() * 1.5 + wo.x()).abs() < 1e-12);
+        assert!(wi.z() < 0.0);
+        assert!((reflect(wo, n) - Vec3::new(-0.6, 0.0, 0.8)).length() < 1e-12);
+    }
+
+    #[test]
+    fn metals_reflect_more_at_grazing_angles() {
+        // Gold's complex refractive index in the red channel.
+        let (eta, k) = (0.18, 3.42);
+        let normal = fresnel_conductor_channel(1.0, eta, k);
+        let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+        assert!((normal - expected).abs() < 1e-12);
+        assert!(fresnel_conductor_channel(0.1, eta, k) > normal);
+        assert!((fresnel_conductor_channel(0.0, eta, k) - 1.0).abs() < 1e-12);
+    }
+}
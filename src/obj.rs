@@ -0,0 +1,693 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    hittable_list::HittableList,
+    material::*,
+    texture::ImageTexture,
+    triangle::{MeshError, TriangleMesh},
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ObjError {
+    fn new(path: &Path, line: Option<usize>, message: impl Into<String>) -> Self {
+        ObjError {
+            path: path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+// Material properties read from an MTL file, before they are mapped onto one of the renderer's
+// materials.
+#[derive(Clone)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f64,
+    pub ior: f64,
+    pub dissolve: f64,
+    pub illum: Option<u32>,
+    pub diffuse_map: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        MtlMaterial {
+            name: name.to_string(),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            illum: None,
+            diffuse_map: None,
+        }
+    }
+
+    pub fn to_material(&self) -> Result<Arc<dyn Scatter>, String> {
+        // Transparent surfaces become glass, surfaces whose specular color dominates become
+        // metal, and everything else is diffuse. The Phong exponent is turned into a roughness
+        // the same way it is usually converted to a microfacet alpha. A diffuse texture map,
+        // when present, takes the place of the diffuse color.
+        let max_component = |c: Color| c.x().max(c.y()).max(c.z());
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, Some(4 | 6 | 7 | 9));
+        let specular = max_component(self.specular);
+        if transparent {
+            Ok(Arc::new(Dielectric::new(self.ior)))
+        } else if specular > 0.0
+            && (self.illum == Some(3) || specular > max_component(self.diffuse))
+        {
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().clamp(0.0, 1.0);
+            Ok(Arc::new(Metal::new(self.specular, fuzz)))
+        } else if let Some(map) = &self.diffuse_map {
+            let texture = ImageTexture::open(map).map_err(|e| {
+                format!(
+                    "material `{}`: failed to load `{}`: {e}",
+                    self.name,
+                    map.display()
+                )
+            })?;
+            Ok(Arc::new(Lambertian::from_texture(Arc::new(texture))))
+        } else {
+            Ok(Arc::new(Lambertian::new(self.diffuse)))
+        }
+    }
+}
+
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<Vec<MtlMaterial>, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ObjError::new(path, None, e.to_string()))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    for (index, raw_line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let err = |message: String| ObjError::new(path, Some(line_number), message);
+        let mut tokens = Line::new(raw_line);
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens.rest();
+            if name.is_empty() {
+                return Err(err("`newmtl` requires a material name".to_string()));
+            }
+            materials.push(MtlMaterial::new(name));
+            continue;
+        }
+
+        let Some(current) = materials.last_mut() else {
+            if matches!(
+                keyword,
+                "Kd" | "Ks" | "Ns" | "Ni" | "d" | "Tr" | "illum" | "map_Kd"
+            ) {
+                return Err(err(format!("`{keyword}` appears before any `newmtl`")));
+            }
+            continue;
+        };
+        match keyword {
+            "Kd" => current.diffuse = tokens.color().map_err(err)?,
+            "Ks" => current.specular = tokens.color().map_err(err)?,
+            "Ns" => current.shininess = tokens.float().map_err(err)?,
+            "Ni" => current.ior = tokens.float().map_err(err)?,
+            "d" => current.dissolve = tokens.float().map_err(err)?,
+            "Tr" => current.dissolve = 1.0 - tokens.float().map_err(err)?,
+            "illum" => {
+                let illum = tokens.float().map_err(err)?;
+                current.illum = Some(illum as u32);
+            }
+            "map_Kd" => {
+                // Options such as `-s` or `-o` may precede the file name, which always comes
+                // last.
+                let file = tokens.rest().split_whitespace().last().unwrap_or("");
+                if file.is_empty() {
+                    return Err(err("`map_Kd` requires a file name".to_string()));
+                }
+                current.diffuse_map = Some(base_dir.join(file));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+#[derive(Clone, Copy)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct Face {
+    vertices: Vec<FaceVertex>,
+    smoothing_group: u32,
+}
+
+// Faces are batched into one mesh per group and material combination.
+struct FaceBatch {
+    material: Arc<dyn Scatter>,
+    faces: Vec<Face>,
+}
+
+// How the normal of a mesh vertex is obtained, which also decides whether two face corners
+// referencing the same position can share a vertex.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    Explicit(usize),
+    Smooth(u32),
+    Flat(usize),
+}
+
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    default_material: Arc<dyn Scatter>,
+) -> Result<HittableList, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ObjError::new(path, None, e.to_string()))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<[f64; 2]> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Scatter>> = HashMap::new();
+
+    let mut batches: Vec<FaceBatch> = Vec::new();
+    let mut batch_index: HashMap<(String, String), usize> = HashMap::new();
+    let mut group = String::new();
+    let mut material_name = String::new();
+    let mut material = default_material;
+    let mut smoothing_group = 0;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let err = |message: String| ObjError::new(path, Some(line_number), message);
+        let mut tokens = Line::new(raw_line);
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        match keyword {
+            "v" => positions.push(tokens.vec3().map_err(err)?),
+            "vn" => normals.push(tokens.vec3().map_err(err)?),
+            "vt" => {
+                let u = tokens.float().map_err(err)?;
+                let v = tokens.optional_float().map_err(err)?.unwrap_or(0.0);
+                uvs.push([u, v]);
+            }
+            "f" => {
+                let mut vertices = Vec::new();
+                for token in tokens.by_ref() {
+                    let vertex =
+                        parse_face_vertex(token, positions.len(), uvs.len(), normals.len())
+                            .map_err(err)?;
+                    vertices.push(vertex);
+                }
+                if vertices.len() < 3 {
+                    return Err(err("a face needs at least three vertices".to_string()));
+                }
+
+                let key = (group.clone(), material_name.clone());
+                let batch = *batch_index.entry(key).or_insert_with(|| {
+                    batches.push(FaceBatch {
+                        material: material.clone(),
+                        faces: Vec::new(),
+                    });
+                    batches.len() - 1
+                });
+                batches[batch].faces.push(Face {
+                    vertices,
+                    smoothing_group,
+                });
+            }
+            "g" | "o" => group = tokens.rest().to_string(),
+            "s" => {
+                // `s on` puts faces in a single group, like `s 1`.
+                smoothing_group = match tokens.next() {
+                    None | Some("off") => 0,
+                    Some("on") => 1,
+                    Some(value) => value
+                        .parse()
+                        .map_err(|_| err(format!("invalid smoothing group `{value}`")))?,
+                };
+            }
+            "mtllib" => {
+                for file in tokens.by_ref() {
+                    let mtl_path = base_dir.join(file);
+                    let library = load_mtl(&mtl_path)
+                        .map_err(|e| err(format!("failed to load material library: {e}")))?;
+                    for mtl in library {
+                        materials.insert(mtl.name.clone(), mtl.to_material().map_err(err)?);
+                    }
+                }
+            }
+            "usemtl" => {
+                let name = tokens.rest();
+                material = materials
+                    .get(name)
+                    .ok_or_else(|| err(format!("unknown material `{name}`")))?
+                    .clone();
+                material_name = name.to_string();
+            }
+            _ => {}
+        }
+    }
+
+    let mut world = HittableList::new();
+    for batch in batches {
+        let mesh = build_mesh(batch, &positions, &normals, &uvs)
+            .map_err(|e| ObjError::new(path, None, e.to_string()))?;
+        world.add(Arc::new(mesh));
+    }
+
+    Ok(world)
+}
+
+fn parse_face_vertex(
+    token: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, String> {
+    // Face vertices come as `v`, `v/vt`, `v//vn` or `v/vt/vn`, with 1-based indices, or
+    // negative indices counting back from the most recently defined element.
+    let resolve = |field: &str, count: usize, what: &str| -> Result<usize, String> {
+        let index: i64 = field
+            .parse()
+            .map_err(|_| format!("invalid {what} index `{field}` in `{token}`"))?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(format!("{what} index {index} is out of range"));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut fields = token.split('/');
+    let position = resolve(fields.next().unwrap_or(""), position_count, "vertex")?;
+    let uv = match fields.next() {
+        None | Some("") => None,
+        Some(field) => Some(resolve(field, uv_count, "texture coordinate")?),
+    };
+    let normal = match fields.next() {
+        None | Some("") => None,
+        Some(field) => Some(resolve(field, normal_count, "normal")?),
+    };
+    if fields.next().is_some() {
+        return Err(format!("malformed face vertex `{token}`"));
+    }
+
+    Ok(FaceVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+fn triangulate(corners: &[usize]) -> impl Iterator<Item = [usize; 3]> + '_ {
+    // Triangulate polygons as a fan around their first vertex.
+    (1..corners.len() - 1).map(move |i| [corners[0], corners[i], corners[i + 1]])
+}
+
+fn build_mesh(
+    batch: FaceBatch,
+    positions: &[Point3],
+    normals: &[Vec3],
+    uvs: &[[f64; 2]],
+) -> Result<TriangleMesh, MeshError> {
+    let needs_uvs = batch
+        .faces
+        .iter()
+        .flat_map(|f| &f.vertices)
+        .any(|v| v.uv.is_some());
+    let needs_normals = batch
+        .faces
+        .iter()
+        .any(|f| f.smoothing_group != 0 || f.vertices.iter().any(|v| v.normal.is_some()));
+
+    // Face normals, computed with Newell's method so that non-planar polygons still get a
+    // sensible average. They are area weighted, which is what smoothing wants.
+    let face_normal = |face: &Face| {
+        let mut n = Vec3::default();
+        for (i, a) in face.vertices.iter().enumerate() {
+            let b = &face.vertices[(i + 1) % face.vertices.len()];
+            n += positions[a.position].cross(positions[b.position]);
+        }
+        n
+    };
+
+    let mut smoothed: HashMap<(usize, u32), Vec3> = HashMap::new();
+    for face in batch.faces.iter().filter(|f| f.smoothing_group != 0) {
+        let n = face_normal(face);
+        for v in face.vertices.iter().filter(|v| v.normal.is_none()) {
+            *smoothed
+                .entry((v.position, face.smoothing_group))
+                .or_default() += n;
+        }
+    }
+
+    let mut mesh_positions = Vec::new();
+    let mut mesh_normals = Vec::new();
+    let mut mesh_uvs = Vec::new();
+    let mut mesh_faces = Vec::new();
+    let mut vertex_index: HashMap<(usize, Option<usize>, NormalSource), usize> = HashMap::new();
+
+    for (face_index, face) in batch.faces.iter().enumerate() {
+        let flat_normal = face_normal(face);
+        let mut corners = Vec::with_capacity(face.vertices.len());
+        for v in &face.vertices {
+            let source = match (v.normal, face.smoothing_group) {
+                (Some(n), _) => NormalSource::Explicit(n),
+                (None, 0) => NormalSource::Flat(face_index),
+                (None, group) => NormalSource::Smooth(group),
+            };
+            let key = (v.position, v.uv, source);
+            let index = *vertex_index.entry(key).or_insert_with(|| {
+                mesh_positions.push(positions[v.position]);
+                if needs_uvs {
+                    mesh_uvs.push(v.uv.map_or([0.0, 0.0], |uv| uvs[uv]));
+                }
+                if needs_normals {
+                    let n = match source {
+                        NormalSource::Explicit(n) => normals[n],
+                        NormalSource::Smooth(group) => smoothed[&(v.position, group)],
+                        NormalSource::Flat(_) => flat_normal,
+                    };
+                    mesh_normals.push(if n.near_zero() { n } else { n.normalized() });
+                }
+                mesh_positions.len() - 1
+            });
+            corners.push(index);
+        }
+
+        mesh_faces.extend(triangulate(&corners));
+    }
+
+    TriangleMesh::new(
+        mesh_positions,
+        mesh_normals,
+        mesh_uvs,
+        mesh_faces,
+        batch.material,
+    )
+}
+
+// Whitespace separated tokens of a line, with comments stripped.
+struct Line<'a> {
+    content: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn new(line: &'a str) -> Self {
+        // A `#` only starts a comment at the start of the line or of a token, so that it may
+        // appear within file names.
+        let end = line
+            .char_indices()
+            .find(|&(i, c)| {
+                c == '#'
+                    && line[..i]
+                        .chars()
+                        .next_back()
+                        .is_none_or(char::is_whitespace)
+            })
+            .map_or(line.len(), |(i, _)| i);
+        Line {
+            content: line[..end].trim(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        self.content.trim()
+    }
+
+    fn optional_float(&mut self) -> Result<Option<f64>, String> {
+        self.next()
+            .map(|token| {
+                token
+                    .parse()
+                    .map_err(|_| format!("expected a number, found `{token}`"))
+            })
+            .transpose()
+    }
+
+    fn float(&mut self) -> Result<f64, String> {
+        self.optional_float()?
+            .ok_or_else(|| "expected a number".to_string())
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn color(&mut self) -> Result<Color, String> {
+        // A single value stands for a grey color.
+        let r = self.float()?;
+        match self.optional_float()? {
+            Some(g) => Ok(Color::new(r, g, self.float()?)),
+            None => Ok(Color::new(r, r, r)),
+        }
+    }
+}
+
+impl<'a> Iterator for Line<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let content = self.content.trim_start();
+        if content.is_empty() {
+            return None;
+        }
+        let end = content.find(char::is_whitespace).unwrap_or(content.len());
+        let (token, rest) = content.split_at(end);
+        self.content = rest;
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::{HitRecord, Hittable},
+        interval::Interval,
+        ray::Ray,
+    };
+
+    // A directory of test files, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn join(&self, file: &str) -> PathBuf {
+            self.0.join(file)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir =
+            std::env::temp_dir().join(format!("ray_tracer_obj_{name}_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        TempDir(dir)
+    }
+
+    fn load(name: &str, obj: &str) -> Result<HittableList, ObjError> {
+        let dir = write_files(name, &[("mesh.obj", obj)]);
+        load_obj(
+            dir.join("mesh.obj"),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    fn hit(world: &HittableList, x: f64, y: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        world
+            .hit(&ray, Interval::from_values(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    fn hits(world: &HittableList, x: f64, y: f64) -> bool {
+        hit(world, x, y).is_some()
+    }
+
+    fn vertex(token: &str) -> Result<(usize, Option<usize>, Option<usize>), String> {
+        parse_face_vertex(token, 4, 3, 2).map(|v| (v.position, v.uv, v.normal))
+    }
+
+    #[test]
+    fn face_vertex_forms() {
+        assert_eq!(vertex("2"), Ok((1, None, None)));
+        assert_eq!(vertex("2/3"), Ok((1, Some(2), None)));
+        assert_eq!(vertex("2/3/1"), Ok((1, Some(2), Some(0))));
+        assert_eq!(vertex("4//2"), Ok((3, None, Some(1))));
+        assert!(vertex("1/1/1/1").is_err());
+        assert!(vertex("a").is_err());
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        assert_eq!(vertex("-1"), Ok((3, None, None)));
+        assert_eq!(vertex("-4/-3/-2"), Ok((0, Some(0), Some(0))));
+        assert_eq!(vertex("-1//-1"), Ok((3, None, Some(1))));
+    }
+
+    #[test]
+    fn indices_out_of_range_are_rejected() {
+        assert_eq!(
+            vertex("0"),
+            Err("vertex index 0 is out of range".to_string())
+        );
+        assert_eq!(
+            vertex("5"),
+            Err("vertex index 5 is out of range".to_string())
+        );
+        assert_eq!(
+            vertex("-5"),
+            Err("vertex index -5 is out of range".to_string())
+        );
+        assert_eq!(
+            vertex("1/4"),
+            Err("texture coordinate index 4 is out of range".to_string())
+        );
+        assert_eq!(
+            vertex("1//3"),
+            Err("normal index 3 is out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn out_of_range_errors_report_their_line() {
+        let error = load("range", "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(5));
+        assert_eq!(error.message, "vertex index 4 is out of range");
+        assert!(error
+            .to_string()
+            .ends_with("mesh.obj:5: vertex index 4 is out of range"));
+
+        // Relative indices only see the vertices defined before the face.
+        let error = load("relative", "v 0 0 0\nv 1 0 0\nf -1 -2 -3\nv 0 1 0\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(3));
+        assert_eq!(error.message, "vertex index -3 is out of range");
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_a_fan() {
+        assert_eq!(
+            triangulate(&[7, 8, 9, 10, 11]).collect::<Vec<_>>(),
+            vec![[7, 8, 9], [7, 9, 10], [7, 10, 11]]
+        );
+
+        // A hexagon covers its whole inside and nothing beyond it.
+        let world = load(
+            "fan",
+            "v 1 0 0\nv 0.5 0.866 0\nv -0.5 0.866 0\nv -1 0 0\nv -0.5 -0.866 0\nv 0.5 -0.866 0\n\
+             f -6 -5 -4 -3 -2 -1\n",
+        )
+        .unwrap();
+        for (x, y) in [(0.0, 0.0), (0.9, 0.0), (-0.9, 0.0), (0.0, 0.8), (0.4, -0.8)] {
+            assert!(hits(&world, x, y), "missed ({x}, {y})");
+        }
+        for (x, y) in [(1.1, 0.0), (0.0, 0.9), (-0.9, 0.5)] {
+            assert!(!hits(&world, x, y), "hit ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn comments_start_at_a_token() {
+        let dir = write_files(
+            "comments",
+            &[
+                ("m#1.mtl", "newmtl red # a comment\nKd 1 0 0\n"),
+                (
+                    "mesh.obj",
+                    "# a triangle\nmtllib m#1.mtl\nv 0 0 0 # origin\nv 1 0 0\nv 0 1 0\t# tab\n\
+                     usemtl red\nf 1 2 3 # the face\n",
+                ),
+            ],
+        );
+        let world = load_obj(
+            dir.join("mesh.obj"),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )
+        .unwrap();
+        assert!(hits(&world, 0.2, 0.2));
+
+        let mut line = Line::new("usemtl a#b #c");
+        assert_eq!(line.next(), Some("usemtl"));
+        assert_eq!(line.rest(), "a#b");
+    }
+
+    // A roof of two quads meeting at a ridge along the y axis.
+    const ROOF: &str = "v -1 -1 0\nv -1 1 0\nv 0 -1 0.5\nv 0 1 0.5\nv 1 -1 0\nv 1 1 0\n";
+
+    #[test]
+    fn smoothing_groups_average_normals() {
+        let flat_slope = Vec3::new(-1.0, 0.0, 2.0).normalized();
+        let normal_at = |world: &HittableList, x: f64| hit(world, x, 0.0).unwrap().normal;
+
+        // Without smoothing, every point of a face has its face normal.
+        let world = load("flat", &format!("{ROOF}s off\nf 1 3 4 2\nf 3 5 6 4\n")).unwrap();
+        for x in [-0.9, -0.5, -0.05] {
+            assert!((normal_at(&world, x) - flat_slope).length() < 1e-9);
+        }
+
+        // With it, the normals of the shared ridge vertices are averaged, so the normal turns
+        // smoothly towards straight up on the ridge.
+        for s in ["1", "on"] {
+            let world = load("smooth", &format!("{ROOF}s {s}\nf 1 3 4 2\nf 3 5 6 4\n")).unwrap();
+            let near_edge = normal_at(&world, -0.95);
+            let middle = normal_at(&world, -0.5);
+            let near_ridge = normal_at(&world, -0.05);
+            assert!((near_edge - flat_slope).length() < 0.05);
+            assert!(near_edge.x() < middle.x() && middle.x() < near_ridge.x());
+            assert!((near_ridge - Vec3::new(0.0, 0.0, 1.0)).length() < 0.05);
+            assert!((normal_at(&world, 0.05).x() + near_ridge.x()).abs() < 1e-9);
+        }
+
+        // Faces in different groups keep their own normals along the shared edge.
+        let world = load("groups", &format!("{ROOF}s 1\nf 1 3 4 2\ns 2\nf 3 5 6 4\n")).unwrap();
+        assert!((normal_at(&world, -0.05) - flat_slope).length() < 1e-9);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::vec3::Vec3;
+
+// An orthonormal basis whose w axis is a given direction, typically a surface normal.
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new(n: Vec3) -> Self {
+        let w = n.normalized();
+        // Pick any vector that isn't parallel to w to build the other two axes from.
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).normalized();
+        let u = w.cross(v);
+
+        Onb { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    pub fn transform(&self, v: Vec3) -> Vec3 {
+        // Transform from basis coordinates to local space.
+        (v[0] * self.axis[0]) + (v[1] * self.axis[1]) + (v[2] * self.axis[2])
+    }
+
+    pub fn to_basis(&self, v: Vec3) -> Vec3 {
+        // Transform from local space to basis coordinates.
+        Vec3::new(
+            v.dot(self.axis[0]),
+            v.dot(self.axis[1]),
+            v.dot(self.axis[2]),
+        )
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::framebuffer::Framebuffer;
+
+#[derive(Debug)]
+pub enum OutputError {
+    UnsupportedFormat(String),
+    Io(io::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::UnsupportedFormat(extension) => write!(
+                f,
+                "unsupported output format `{extension}`, expected one of: ppm, png, pfm"
+            ),
+            OutputError::Io(e) => write!(f, "{e}"),
+            OutputError::Image(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for OutputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OutputError::UnsupportedFormat(_) => None,
+            OutputError::Io(e) => Some(e),
+            OutputError::Image(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for OutputError {
+    fn from(e: io::Error) -> Self {
+        OutputError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, OutputError> {
+        // The format is picked from the file extension of the output path.
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Ok(OutputFormat::Ppm),
+            "png" => Ok(OutputFormat::Png),
+            "pfm" => Ok(OutputFormat::Pfm),
+            _ => Err(OutputError::UnsupportedFormat(extension)),
+        }
+    }
+}
+
+pub fn write_image(path: impl AsRef<Path>, image: &Framebuffer) -> Result<(), OutputError> {
+    let path = path.as_ref();
+    match OutputFormat::from_path(path)? {
+        OutputFormat::Ppm => write_ppm(path, image),
+        OutputFormat::Png => write_png(path, image),
+        OutputFormat::Pfm => write_pfm(path, image),
+    }
+}
+
+pub fn write_ppm(path: impl AsRef<Path>, image: &Framebuffer) -> Result<(), OutputError> {
+    // Binary (P6) PPM with gamma-encoded 8-bit samples.
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    out.write_all(&image.to_rgb8())?;
+    out.flush()?;
+    Ok(())
+}
+
+pub fn write_png(path: impl AsRef<Path>, image: &Framebuffer) -> Result<(), OutputError> {
+    image::save_buffer_with_format(
+        path,
+        &image.to_rgb8(),
+        image.width(),
+        image.height(),
+        image::ExtendedColorType::Rgb8,
+        image::ImageFormat::Png,
+    )
+    .map_err(OutputError::Image)
+}
+
+pub fn write_pfm(path: impl AsRef<Path>, image: &Framebuffer) -> Result<(), OutputError> {
+    // Portable float map: linear 32-bit float samples, little endian (signalled by the negative
+    // scale), with the rows stored from the bottom of the image up.
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for row in image.pixels().chunks(image.width() as usize).rev() {
+        for pixel in row {
+            for channel in [pixel.x(), pixel.y(), pixel.z()] {
+                out.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Color;
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(OutputFormat::from_path("a.ppm").unwrap(), OutputFormat::Ppm);
+        assert_eq!(
+            OutputFormat::from_path("dir/a.PNG").unwrap(),
+            OutputFormat::Png
+        );
+        assert_eq!(
+            OutputFormat::from_path("a.b.pfm").unwrap(),
+            OutputFormat::Pfm
+        );
+        for path in ["a.jpg", "a", "png"] {
+            assert!(matches!(
+                OutputFormat::from_path(path),
+                Err(OutputError::UnsupportedFormat(_))
+            ));
+        }
+        assert_eq!(
+            OutputFormat::from_path("a.exr").unwrap_err().to_string(),
+            "unsupported output format `exr`, expected one of: ppm, png, pfm"
+        );
+    }
+
+    #[test]
+    fn pfm_is_little_endian_from_the_bottom_row_up() {
+        let mut image = Framebuffer::new(2, 2);
+        image.set(0, 0, Color::new(1.0, 2.0, 3.0));
+        image.set(1, 1, Color::new(4.0, 5.5, -6.0));
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_output_{}.pfm", std::process::id()));
+        write_image(&path, &image).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let samples: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                0.0, 0.0, 0.0, 4.0, 5.5, -6.0, // the bottom row
+                1.0, 2.0, 3.0, 0.0, 0.0, 0.0, // the top row
+            ]
+        );
+    }
+
+    #[test]
+    fn ppm_holds_the_rgb8_samples() {
+        let mut image = Framebuffer::new(2, 1);
+        image.set(1, 0, Color::new(1.0, 0.25, 0.0));
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_output_{}.ppm", std::process::id()));
+        write_image(&path, &image).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes, b"P6\n2 1\n255\n\0\0\0\xff\x80\0");
+    }
+}
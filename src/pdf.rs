@@ -0,0 +1,479 @@
+use std::f64::consts::PI;
+
+use crate::{
+    environment::EnvironmentMap,
+    hittable::Hittable,
+    microfacet::{fresnel_dielectric, reflect, refract, Ggx},
+    onb::Onb,
+    random::random_double,
+    vec3::{Point3, Vec3},
+};
+
+// A probability density over directions, which can be sampled and evaluated.
+pub trait Pdf {
+    // The density, with respect to solid angle, of sampling the given direction.
+    fn value(&self, direction: Vec3) -> f64;
+
+    // Returns a random direction distributed according to this density.
+    fn generate(&self) -> Vec3;
+}
+
+// Uniform over all directions.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+// Proportional to the cosine of the angle to a normal, over the hemisphere around it.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: Vec3) -> Self {
+        CosinePdf { uvw: Onb::new(w) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine_theta = direction.normalized().dot(self.uvw.w());
+        f64::max(0.0, cosine_theta / PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.transform(Vec3::random_cosine_direction())
+    }
+}
+
+// Directions from an origin towards the objects, typically the lights of a scene.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3) -> Self {
+        HittablePdf { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.objects.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.objects.random(self.origin)
+    }
+}
+
+// Directions towards the bright parts of an environment map.
+pub struct EnvironmentPdf<'a> {
+    map: &'a EnvironmentMap,
+}
+
+impl<'a> EnvironmentPdf<'a> {
+    pub fn new(map: &'a EnvironmentMap) -> Self {
+        EnvironmentPdf { map }
+    }
+}
+
+impl Pdf for EnvironmentPdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.map.pdf_value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.map.random()
+    }
+}
+
+// An even mix of two densities.
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        MixturePdf { p: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if random_double() < 0.5 {
+            self.p[0].generate()
+        } else {
+            self.p[1].generate()
+        }
+    }
+}
+
+// The directions of fuzzy metal reflections: towards a random point on a sphere of radius
+// `fuzz` centered on the tip of the unit mirror direction.
+pub struct FuzzyReflectionPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl FuzzyReflectionPdf {
+    pub fn new(reflected: Vec3, fuzz: f64) -> Self {
+        FuzzyReflectionPdf {
+            reflected: reflected.normalized(),
+            fuzz,
+        }
+    }
+}
+
+impl Pdf for FuzzyReflectionPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        // Points are uniform over the sphere's area. Each point where the direction crosses the
+        // sphere contributes the area density converted to solid angle, distance^2 / cos.
+        let direction = direction.normalized();
+        let b = direction.dot(self.reflected);
+        let discriminant = b * b - 1.0 + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        let area_density = 1.0 / (4.0 * PI * self.fuzz * self.fuzz);
+        [b - sqrtd, b + sqrtd]
+            .into_iter()
+            .filter(|&t| t > 0.0)
+            .map(|t| {
+                let normal = (t * direction - self.reflected) / self.fuzz;
+                let cosine = direction.dot(normal).abs();
+                area_density * t * t / cosine
+            })
+            .sum()
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.reflected + self.fuzz * Vec3::random_unit_vector()
+    }
+}
+
+// Mirror reflections about the microfacet normals a rough surface shows to the viewer.
+pub struct GgxReflectionPdf {
+    uvw: Onb,
+    // Unit direction towards the viewer, in the basis of the surface.
+    wo: Vec3,
+    ggx: Ggx,
+}
+
+impl GgxReflectionPdf {
+    pub fn new(uvw: Onb, wo: Vec3, ggx: Ggx) -> Self {
+        GgxReflectionPdf { uvw, wo, ggx }
+    }
+}
+
+impl Pdf for GgxReflectionPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        // The density of the normals, times the Jacobian of reflecting about them. Reflections
+        // off steep microfacets may point below the surface.
+        let wi = self.uvw.to_basis(direction.normalized());
+        ggx_reflection_pdf(&self.ggx, self.wo, wi)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let h = self.ggx.sample_visible_normal(self.wo);
+        self.uvw.transform(reflect(self.wo, h))
+    }
+}
+
+fn ggx_reflection_pdf(ggx: &Ggx, wo: Vec3, wi: Vec3) -> f64 {
+    let h = wo + wi;
+    if h.near_zero() {
+        return 0.0;
+    }
+    let h = h.normalized();
+    let cos_h = wo.dot(h);
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+    ggx.visible_normal_pdf(wo, h) / (4.0 * cos_h)
+}
+
+// The directions light takes off a rough interface between dielectrics: reflected or refracted
+// by the microfacets seen by the viewer, in proportion to their Fresnel reflectance.
+pub struct GgxDielectricPdf {
+    uvw: Onb,
+    // Unit direction towards the viewer, in the basis of the surface.
+    wo: Vec3,
+    ggx: Ggx,
+    // The refractive index beyond the surface over the one on the viewer's side.
+    eta: f64,
+}
+
+impl GgxDielectricPdf {
+    pub fn new(uvw: Onb, wo: Vec3, ggx: Ggx, eta: f64) -> Self {
+        GgxDielectricPdf { uvw, wo, ggx, eta }
+    }
+}
+
+impl Pdf for GgxDielectricPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        // Rough surfaces may reflect below themselves or refract back above, so a direction
+        // can come from either choice, whichever side of the surface it is on.
+        let wi = self.uvw.to_basis(direction.normalized());
+        let mut density = 0.0;
+
+        let h = (self.wo + wi).normalized();
+        if h.z() > 0.0 {
+            let reflectance = fresnel_dielectric(self.wo.dot(h), self.eta);
+            density += reflectance * ggx_reflection_pdf(&self.ggx, self.wo, wi);
+        }
+
+        // The microfacet normal that refracts wo into wi, and the Jacobian of refracting about it.
+        let mut h = (self.eta * wi + self.wo).normalized();
+        if h.z() < 0.0 {
+            h = -h;
+        }
+        if h.dot(wi) < 0.0 && h.dot(self.wo) > 0.0 {
+            let denominator = (wi.dot(h) + self.wo.dot(h) / self.eta).powi(2);
+            let transmittance = 1.0 - fresnel_dielectric(self.wo.dot(h), self.eta);
+            density += transmittance * self.ggx.visible_normal_pdf(self.wo, h) * wi.dot(h).abs()
+                / denominator;
+        }
+
+        density
+    }
+
+    fn generate(&self) -> Vec3 {
+        let h = self.ggx.sample_visible_normal(self.wo);
+        let reflectance = fresnel_dielectric(self.wo.dot(h), self.eta);
+        let wi = if random_double() < reflectance {
+            reflect(self.wo, h)
+        } else {
+            refract(self.wo, h, self.eta).unwrap_or_else(|| reflect(self.wo, h))
+        };
+        self.uvw.transform(wi)
+    }
+}
+
+// A mix of densities, each picked with a probability in proportion to its weight.
+pub struct WeightedMixturePdf {
+    pdfs: Vec<(f64, Box<dyn Pdf>)>,
+    total: f64,
+}
+
+impl WeightedMixturePdf {
+    pub fn new() -> Self {
+        WeightedMixturePdf {
+            pdfs: Vec::new(),
+            total: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, weight: f64, pdf: Box<dyn Pdf>) {
+        // Densities without weight are left out.
+        if weight > 0.0 {
+            self.total += weight;
+            self.pdfs.push((weight, pdf));
+        }
+    }
+}
+
+impl Default for WeightedMixturePdf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pdf for WeightedMixturePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        if self.pdfs.is_empty() {
+            return 0.0;
+        }
+        self.pdfs
+            .iter()
+            .map(|(weight, pdf)| weight * pdf.value(direction))
+            .sum::<f64>()
+            / self.total
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut target = random_double() * self.total;
+        for (weight, pdf) in &self.pdfs {
+            if target < *weight {
+                return pdf.generate();
+            }
+            target -= weight;
+        }
+        match self.pdfs.last() {
+            Some((_, pdf)) => pdf.generate(),
+            None => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::random;
+
+    // Directions are binned on a grid of equal solid angle: evenly in z, and evenly in the angle
+    // around the z axis.
+    const Z_BINS: usize = 10;
+    const PHI_BINS: usize = 20;
+
+    fn bin(direction: Vec3) -> usize {
+        let d = direction.normalized();
+        let a = ((0.5 * (d.z() + 1.0) * Z_BINS as f64) as usize).min(Z_BINS - 1);
+        let phi = d.y().atan2(d.x()).rem_euclid(2.0 * PI);
+        let b = ((phi / (2.0 * PI) * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+        a * PHI_BINS + b
+    }
+
+    // Checks that the density integrates to one over the sphere of directions, and that
+    // `generate` picks directions as often as `value` says it does.
+    pub(crate) fn check_pdf(pdf: &dyn Pdf) {
+        // Integrate the density over each bin, with a point at a random place in each cell of a
+        // grid even in the polar angle, which covers the bins around the poles as finely as the
+        // others. Densities can spike towards infinity, as at the rim of the cone of a fuzzy
+        // reflection, so the error of each integral is estimated along with it.
+        const STEPS: usize = 32;
+        const SAMPLES: f64 = (STEPS * STEPS) as f64;
+        random::seed(1);
+        let mut expected = vec![(0.0, 0.0); Z_BINS * PHI_BINS];
+        for (index, (probability, error)) in expected.iter_mut().enumerate() {
+            let (a, b) = (index / PHI_BINS, index % PHI_BINS);
+            let z_edge = |a: usize| 2.0 * a as f64 / Z_BINS as f64 - 1.0;
+            let (theta0, theta1) = (z_edge(a + 1).acos(), z_edge(a).acos());
+            let d_theta = (theta1 - theta0) / STEPS as f64;
+            let d_phi = 2.0 * PI / (PHI_BINS * STEPS) as f64;
+            let (mut sum, mut sum_squares) = (0.0, 0.0);
+            for i in 0..STEPS {
+                for j in 0..STEPS {
+                    let theta = theta0 + (i as f64 + random_double()) * d_theta;
+                    let phi = ((b * STEPS + j) as f64 + random_double()) * d_phi;
+                    let direction = Vec3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    // The estimate of the whole bin's integral from this point alone.
+                    let estimate = pdf.value(direction) * theta.sin() * d_theta * d_phi * SAMPLES;
+                    sum += estimate;
+                    sum_squares += estimate * estimate;
+                }
+            }
+            *probability = sum / SAMPLES;
+            *error = ((sum_squares / SAMPLES - *probability * *probability) / SAMPLES).sqrt();
+        }
+        let total: f64 = expected.iter().map(|(p, _)| p).sum();
+        let total_error = expected.iter().map(|(_, e)| e * e).sum::<f64>().sqrt();
+        assert!(
+            (total - 1.0).abs() < 0.01 + 5.0 * total_error,
+            "the density integrates to {total} ± {total_error}"
+        );
+
+        let n = 200_000;
+        let mut counts = vec![0; Z_BINS * PHI_BINS];
+        for _ in 0..n {
+            counts[bin(pdf.generate())] += 1;
+        }
+        for (index, (&count, &(p, error))) in counts.iter().zip(&expected).enumerate() {
+            let observed = count as f64 / n as f64;
+            let sigma = (p * (1.0 - p) / n as f64).sqrt();
+            assert!(
+                (observed - p).abs() < 5.0 * (sigma + error) + 0.001,
+                "bin {index}: {observed} of the directions were generated, but {p} ± {error} \
+                 expected"
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_pdf() {
+        check_pdf(&SpherePdf);
+    }
+
+    #[test]
+    fn cosine_pdf() {
+        check_pdf(&CosinePdf::new(Vec3::new(0.0, 0.0, 1.0)));
+        check_pdf(&CosinePdf::new(Vec3::new(1.0, -2.0, 0.5)));
+    }
+
+    #[test]
+    fn mixture_pdf() {
+        let cosine = CosinePdf::new(Vec3::new(0.0, 1.0, 1.0));
+        check_pdf(&MixturePdf::new(&cosine, &SpherePdf));
+    }
+
+    #[test]
+    fn fuzzy_reflection_pdf() {
+        check_pdf(&FuzzyReflectionPdf::new(Vec3::new(0.0, 0.0, 1.0), 0.3));
+        check_pdf(&FuzzyReflectionPdf::new(Vec3::new(1.0, 1.0, -0.5), 0.1));
+        // With a fuzz of 1 the sphere passes through the origin of the directions.
+        check_pdf(&FuzzyReflectionPdf::new(Vec3::new(0.0, 2.0, 1.0), 1.0));
+    }
+
+    #[test]
+    fn ggx_reflection_pdf() {
+        let uvw = || Onb::new(Vec3::new(0.0, 0.0, 1.0));
+        let wo = Vec3::new(0.3, -0.2, 1.0).normalized();
+        check_pdf(&GgxReflectionPdf::new(
+            uvw(),
+            wo,
+            Ggx::from_roughness(0.5, 0.0),
+        ));
+        check_pdf(&GgxReflectionPdf::new(
+            uvw(),
+            wo,
+            Ggx::from_roughness(0.7, 0.8),
+        ));
+        // Seen at a grazing angle, many reflections point under the surface.
+        let grazing = Vec3::new(1.0, 0.5, 0.2).normalized();
+        check_pdf(&GgxReflectionPdf::new(
+            uvw(),
+            grazing,
+            Ggx::from_roughness(0.6, 0.0),
+        ));
+    }
+
+    #[test]
+    fn ggx_dielectric_pdf() {
+        let uvw = || Onb::new(Vec3::new(0.0, 0.0, 1.0));
+        let wo = Vec3::new(0.3, -0.2, 1.0).normalized();
+        // Into glass, and out of it, where light beyond the critical angle is reflected.
+        for eta in [1.5, 1.0 / 1.5] {
+            check_pdf(&GgxDielectricPdf::new(
+                uvw(),
+                wo,
+                Ggx::from_roughness(0.5, 0.0),
+                eta,
+            ));
+        }
+        let grazing = Vec3::new(1.0, 0.5, 0.2).normalized();
+        check_pdf(&GgxDielectricPdf::new(
+            uvw(),
+            grazing,
+            Ggx::from_roughness(0.7, 0.6),
+            1.33,
+        ));
+    }
+
+    #[test]
+    fn weighted_mixture_pdf() {
+        let mut mixture = WeightedMixturePdf::new();
+        mixture.add(3.0, Box::new(CosinePdf::new(Vec3::new(0.0, 1.0, 1.0))));
+        // Densities without weight are never picked.
+        mixture.add(0.0, Box::new(CosinePdf::new(Vec3::new(0.0, 0.0, -1.0))));
+        mixture.add(1.0, Box::new(SpherePdf));
+        check_pdf(&mixture);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    random::random_double,
+    vec3::{Point3, Vec3},
+};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise over a lattice of random unit vectors.
+pub struct Perlin {
+    randvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        Perlin {
+            randvec: (0..POINT_COUNT)
+                .map(|_| Vec3::random_in_range(-1.0, 1.0).normalized())
+                .collect(),
+            perm_x: Self::generate_perm(),
+            perm_y: Self::generate_perm(),
+            perm_z: Self::generate_perm(),
+        }
+    }
+
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+
+        let i = p.x().floor() as i64;
+        let j = p.y().floor() as i64;
+        let k = p.z().floor() as i64;
+        let mut c = [[[Vec3::default(); 2]; 2]; 2];
+
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.randvec[self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize]];
+                }
+            }
+        }
+
+        Self::perlin_interp(&c, u, v, w)
+    }
+
+    pub fn turb(&self, p: &Point3, depth: u32) -> f64 {
+        // Sum of noise at increasing frequencies and decreasing amplitudes.
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+
+        accum.abs()
+    }
+
+    fn generate_perm() -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        Self::permute(&mut p);
+        p
+    }
+
+    fn permute(p: &mut [usize]) {
+        for i in (1..p.len()).rev() {
+            let target = (random_double() * (i + 1) as f64) as usize;
+            p.swap(i, target);
+        }
+    }
+
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        // Hermitian smoothing of the interpolation weights avoids grid artifacts.
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+        let mut accum = 0.0;
+
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * corner.dot(weight_v);
+                }
+            }
+        }
+
+        accum
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,345 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    material::*,
+    random::random_double,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// The outline of a planar primitive, in the plane coordinates (alpha, beta) of a point
+// Q + alpha * u + beta * v.
+pub trait PlanarShape: Sync + Send {
+    // Returns the surface texture coordinates of an in-plane point, or None if the point is
+    // outside of the shape.
+    fn interior(alpha: f64, beta: f64) -> Option<(f64, f64)>;
+
+    // Plane coordinates of points whose bounding box encloses the shape.
+    fn extremes() -> &'static [(f64, f64)];
+
+    // The area of the shape, relative to that of the parallelogram spanned by the edges.
+    fn relative_area() -> f64;
+
+    // Returns the plane coordinates of a random point, uniformly distributed over the shape.
+    fn sample() -> (f64, f64);
+}
+
+// The parallelogram spanned by the two edges.
+pub struct Parallelogram;
+
+impl PlanarShape for Parallelogram {
+    fn interior(alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let unit_interval = Interval::from_values(0.0, 1.0);
+        (unit_interval.contains(alpha) && unit_interval.contains(beta)).then_some((alpha, beta))
+    }
+
+    fn extremes() -> &'static [(f64, f64)] {
+        &[(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)]
+    }
+
+    fn relative_area() -> f64 {
+        1.0
+    }
+
+    fn sample() -> (f64, f64) {
+        (random_double(), random_double())
+    }
+}
+
+// The triangle with a corner at the origin and the two edges as its sides.
+pub struct TriangleShape;
+
+impl PlanarShape for TriangleShape {
+    fn interior(alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        (alpha >= 0.0 && beta >= 0.0 && alpha + beta <= 1.0).then_some((alpha, beta))
+    }
+
+    fn extremes() -> &'static [(f64, f64)] {
+        &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]
+    }
+
+    fn relative_area() -> f64 {
+        0.5
+    }
+
+    fn sample() -> (f64, f64) {
+        // Points of the parallelogram beyond the diagonal are folded back onto the triangle.
+        let (alpha, beta) = (random_double(), random_double());
+        if alpha + beta > 1.0 {
+            (1.0 - alpha, 1.0 - beta)
+        } else {
+            (alpha, beta)
+        }
+    }
+}
+
+// The ellipse centered on the origin, with the two edges as its radii.
+pub struct Ellipse;
+
+impl PlanarShape for Ellipse {
+    fn interior(alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        (alpha * alpha + beta * beta <= 1.0).then_some((0.5 * (alpha + 1.0), 0.5 * (beta + 1.0)))
+    }
+
+    fn extremes() -> &'static [(f64, f64)] {
+        &[(-1.0, -1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, 1.0)]
+    }
+
+    fn relative_area() -> f64 {
+        PI
+    }
+
+    fn sample() -> (f64, f64) {
+        let p = Vec3::random_in_unit_disk();
+        (p.x(), p.y())
+    }
+}
+
+pub struct Planar<S: PlanarShape> {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    mat: Arc<dyn Scatter>,
+    bbox: Aabb,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+    shape: std::marker::PhantomData<S>,
+}
+
+pub type Quad = Planar<Parallelogram>;
+pub type PlanarTriangle = Planar<TriangleShape>;
+pub type Disk = Planar<Ellipse>;
+
+impl<S: PlanarShape> Planar<S> {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Scatter>) -> Self {
+        let n = u.cross(v);
+        let normal = n.normalized();
+        let d = normal.dot(q);
+        let w = n / n.dot(n);
+
+        // Compute the bounding box of all the vertices.
+        let bbox = S::extremes()
+            .iter()
+            .map(|&(alpha, beta)| q + alpha * u + beta * v)
+            .fold(Aabb::EMPTY, |bbox, p| {
+                Aabb::from_boxes(&bbox, &Aabb::from_points(p, p))
+            });
+
+        Planar {
+            q,
+            u,
+            v,
+            w,
+            mat,
+            bbox,
+            normal,
+            d,
+            area: S::relative_area() * n.length(),
+            shape: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<S: PlanarShape> Hittable for Planar<S> {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(r.direction());
+
+        // No hit if the ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        // Return false if the hit point parameter t is outside the ray interval.
+        let t = (self.d - self.normal.dot(r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        // Determine if the hit point lies within the planar shape using its plane coordinates.
+        let intersection = r.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = self.w.dot(planar_hitpt_vector.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar_hitpt_vector));
+
+        let Some((u, v)) = S::interior(alpha, beta) else {
+            return false;
+        };
+
+        // Ray hits the 2D shape; set the rest of the hit record and return true.
+        rec.t = t;
+        rec.p = intersection;
+        (rec.u, rec.v) = (u, v);
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &self.normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(
+            &Ray::new(origin, direction),
+            Interval::from_values(0.001, f64::INFINITY),
+            &mut rec,
+        ) {
+            return 0.0;
+        }
+
+        // Convert the uniform density over the area into a density over solid angle.
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        let (alpha, beta) = S::sample();
+        let p = self.q + (alpha * self.u) + (beta * self.v);
+        p - origin
+    }
+}
+
+pub fn make_box(a: Point3, b: Point3, mat: Arc<dyn Scatter>) -> HittableList {
+    // Returns the 3D box (six sides) that contains the two opposite vertices a & b.
+    let mut sides = HittableList::new();
+
+    // Construct the two opposite vertices with the minimum and maximum coordinates.
+    let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+    let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+
+    let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+    let side = |q: Point3, u: Vec3, v: Vec3| Arc::new(Quad::new(q, u, v, mat.clone()));
+    sides.add(side(Point3::new(min.x(), min.y(), max.z()), dx, dy)); // front
+    sides.add(side(Point3::new(max.x(), min.y(), max.z()), -dz, dy)); // right
+    sides.add(side(Point3::new(max.x(), min.y(), min.z()), -dx, dy)); // back
+    sides.add(side(Point3::new(min.x(), min.y(), min.z()), dz, dy)); // left
+    sides.add(side(Point3::new(min.x(), max.y(), max.z()), dx, -dz)); // top
+    sides.add(side(Point3::new(min.x(), min.y(), min.z()), dx, dz)); // bottom
+
+    sides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pdf::{tests::check_pdf, HittablePdf},
+        vec3::Color,
+    };
+
+    fn material() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn hit(object: &impl Hittable, r: &Ray) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        object
+            .hit(r, Interval::from_values(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    // A ray straight down the -z axis through the given point of the z = -1 plane.
+    fn ray_to(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn quad_hit() {
+        let u = Vec3::new(2.0, 0.0, 0.0);
+        let v = Vec3::new(0.0, 1.0, 0.0);
+        let quad = Quad::new(Point3::new(0.0, 0.0, -1.0), u, v, material());
+
+        let rec = hit(&quad, &ray_to(0.5, 0.25)).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        assert!(hit(&quad, &ray_to(2.5, 0.5)).is_none());
+        assert!(hit(&quad, &ray_to(-0.1, 0.5)).is_none());
+        let parallel = Ray::new(Point3::new(0.0, 0.5, -1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(hit(&quad, &parallel).is_none());
+    }
+
+    #[test]
+    fn tiny_quad_hit() {
+        let (u, v) = (Vec3::new(1e-7, 0.0, 0.0), Vec3::new(0.0, 1e-7, 0.0));
+        let quad = Quad::new(Point3::new(0.0, 0.0, -1.0), u, v, material());
+        let rec = hit(&quad, &ray_to(5e-8, 5e-8)).unwrap();
+        assert!((rec.u - 0.5).abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+        assert!(hit(&quad, &ray_to(2e-7, 5e-8)).is_none());
+    }
+
+    #[test]
+    fn disk_and_triangle_outlines() {
+        let (u, v) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let center = Point3::new(0.0, 0.0, -1.0);
+        let disk = Disk::new(center, u, v, material());
+        let rec = hit(&disk, &ray_to(0.0, 0.0)).unwrap();
+        assert!((rec.u - 0.5).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        assert!(hit(&disk, &ray_to(0.7, 0.7)).is_some());
+        assert!(hit(&disk, &ray_to(0.75, 0.75)).is_none());
+
+        let triangle = PlanarTriangle::new(center, u, v, material());
+        assert!(hit(&triangle, &ray_to(0.45, 0.45)).is_some());
+        assert!(hit(&triangle, &ray_to(0.55, 0.55)).is_none());
+    }
+
+    #[test]
+    fn box_is_closed() {
+        let sides = make_box(
+            Point3::new(1.0, 1.0, -3.0),
+            Point3::new(-1.0, -1.0, -1.0),
+            material(),
+        );
+        assert_eq!(sides.objects.len(), 6);
+        // Rays from the center leave through a side, and every normal faces back at them.
+        let center = Point3::new(0.0, 0.0, -2.0);
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.2, 0.3, -1.0),
+        ] {
+            let rec = hit(&sides, &Ray::new(center, direction)).unwrap();
+            assert!(!rec.front_face, "{direction:?}");
+            assert!((rec.p - center).length() >= 1.0 - 1e-12, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn pdf_value_matches_random() {
+        // Close enough to cover a good part of the sphere of directions.
+        let origin = Point3::new(0.2, 0.3, 0.0);
+        let (q, u, v) = (
+            Point3::new(-1.0, -1.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.5, 2.0, 0.5),
+        );
+        check_pdf(&HittablePdf::new(&Quad::new(q, u, v, material()), origin));
+        let center = Point3::new(0.0, 0.0, -0.5);
+        check_pdf(&HittablePdf::new(
+            &Disk::new(center, u, v, material()),
+            origin,
+        ));
+        check_pdf(&HittablePdf::new(
+            &PlanarTriangle::new(q, u, v, material()),
+            origin,
+        ));
+    }
+}
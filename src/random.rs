@@ -0,0 +1,41 @@
+use std::cell::RefCell;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+// Every thread draws from its own generator. It starts out seeded from the OS, and can be
+// reseeded to make renders reproducible.
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+pub fn mix_seed(seed: u64, a: u64, b: u64) -> u64 {
+    // Derives an independent seed from a base seed and two values, such as pixel coordinates,
+    // using the SplitMix64 finalizer.
+    let mut z =
+        seed ^ a.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ b.wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[inline]
+pub fn random_double() -> f64 {
+    // Returns a random real in [0,1).
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+#[inline]
+pub fn random_double_range(min: f64, max: f64) -> f64 {
+    // Returns a random real in [min,max).
+    min + (max - min) * random_double()
+}
+
+#[inline]
+pub fn random_int(min: i64, max: i64) -> i64 {
+    // Returns a random integer in [min,max].
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..=max))
+}
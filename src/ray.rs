@@ -2,14 +2,34 @@ use core::f64;
 
 use crate::vec3::*;
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
+    // The instant the ray was sent at, within the camera's shutter interval.
+    pub tm: f64,
+    // The hero wavelength of a spectral render, in nanometres, which decides how materials
+    // that split light into its colors bend the ray. None when rendering in RGB.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(orig: Point3, dir: Vec3) -> Self {
-        Ray { orig, dir }
+        Ray {
+            orig,
+            dir,
+            tm: 0.0,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_time(orig: Point3, dir: Vec3, tm: f64) -> Self {
+        Ray {
+            orig,
+            dir,
+            tm,
+            wavelength: None,
+        }
     }
 
     pub fn origin(&self) -> Point3 {
@@ -20,6 +40,14 @@ impl Ray {
         self.dir
     }
 
+    pub fn time(&self) -> f64 {
+        self.tm
+    }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
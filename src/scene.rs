@@ -0,0 +1,1811 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    bvh::BvhNode,
+    camera::{Background, Camera},
+    constant_medium::ConstantMedium,
+    environment::EnvironmentMap,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    material::*,
+    matrix::Mat4,
+    obj::{self, ObjError},
+    quad::{make_box, Disk, Quad},
+    spectrum::{RefractiveIndex, MAX_WAVELENGTH, MIN_WAVELENGTH},
+    sphere::Sphere,
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+    thin_film::ThinFilm,
+    transform::Transform,
+    triangle::Triangle,
+    vec3::{Color, Point3, Vec3},
+};
+
+// A renderable scene: the objects in the world and the camera looking at them.
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    // The emissive objects of the world, which the renderer samples directly.
+    pub lights: HittableList,
+    // Lights outside the world, such as point lights, which only light it through shadow rays.
+    pub analytic_lights: Vec<Arc<dyn Light>>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownMaterial { object: usize, name: String },
+    UnknownTexture { setting: String, name: String },
+    Invalid(String),
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SceneError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            SceneError::UnknownMaterial { object, name } => {
+                write!(f, "object {object} uses unknown material `{name}`")
+            }
+            SceneError::UnknownTexture { setting, name } => {
+                write!(f, "{setting} uses unknown texture `{name}`")
+            }
+            SceneError::Invalid(message) => write!(f, "{message}"),
+            SceneError::Obj(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(_, e) => Some(e),
+            SceneError::Parse(_, e) => Some(e),
+            SceneError::Obj(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// The types below mirror the layout of a scene file. They are converted into the renderer's own
+// types once the whole file has been parsed.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default)]
+    render: RenderDescription,
+    #[serde(default)]
+    camera: CameraDescription,
+    #[serde(default)]
+    textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderDescription {
+    aspect_ratio: f64,
+    image_width: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    min_bounces: u32,
+    mis: bool,
+    spectral: bool,
+}
+
+impl Default for RenderDescription {
+    fn default() -> Self {
+        RenderDescription {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            min_bounces: 3,
+            mis: true,
+            spectral: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraDescription {
+    vfov: f64,
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    vup: [f64; 3],
+    defocus_angle: f64,
+    focus_dist: f64,
+    background: BackgroundDescription,
+    shutter_open: f64,
+    shutter_close: f64,
+}
+
+// Either the name of a built-in background, a solid color, or an environment map.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDescription {
+    Named(String),
+    Solid([f64; 3]),
+    Environment(EnvironmentDescription),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDescription {
+    // An equirectangular image, such as a Radiance .hdr file.
+    environment: PathBuf,
+    #[serde(default = "one")]
+    intensity: f64,
+    // Degrees about the vertical axis.
+    #[serde(default)]
+    rotation: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        CameraDescription {
+            vfov: 90.0,
+            lookfrom: [0.0, 0.0, 0.0],
+            lookat: [0.0, 0.0, -1.0],
+            vup: [0.0, 1.0, 0.0],
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            background: BackgroundDescription::Named("sky".to_string()),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+        }
+    }
+}
+
+// Either a constant color, or the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorSource {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+// A refractive index, either constant or varying with the wavelength by Cauchy's equation or
+// the Sellmeier equation, with wavelengths in micrometres.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IndexDescription {
+    Constant(f64),
+    Dispersive(DispersionDescription),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum DispersionDescription {
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+// A thin coating such as a soap film or an oil slick, with its thickness in nanometres.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThinFilmDescription {
+    thickness: f64,
+    refraction_index: f64,
+}
+
+// Either a constant number, or the name of a texture whose luminance gives the number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScalarSource {
+    Value(f64),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: ColorSource,
+        odd: ColorSource,
+    },
+    Image {
+        file: PathBuf,
+        // Whether the image holds data, such as roughness, rather than gamma encoded colors.
+        #[serde(default)]
+        linear: bool,
+    },
+    Noise {
+        scale: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        albedo: ColorSource,
+    },
+    Metal {
+        albedo: ColorSource,
+        fuzz: f64,
+        thin_film: Option<ThinFilmDescription>,
+    },
+    Dielectric {
+        refraction_index: IndexDescription,
+        thin_film: Option<ThinFilmDescription>,
+        #[serde(flatten)]
+        tint: TintDescription,
+    },
+    RoughDielectric {
+        refraction_index: f64,
+        roughness: f64,
+        #[serde(flatten)]
+        tint: TintDescription,
+    },
+    DiffuseLight {
+        emit: ColorSource,
+    },
+    Isotropic {
+        albedo: ColorSource,
+    },
+    Conductor {
+        // Either a preset metal, or its complex refractive index eta + ik per color channel.
+        preset: Option<PresetDescription>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        anisotropy: f64,
+    },
+    Principled(PrincipledDescription),
+}
+
+// How glass absorbs light inside: either by its absorption coefficient, or by the color white
+// light turns after a distance through it. Clear by default.
+#[derive(Deserialize)]
+struct TintDescription {
+    absorption: Option<[f64; 3]>,
+    color: Option<[f64; 3]>,
+    color_distance: Option<f64>,
+}
+
+// The parameters of a principled material, all of which are optional.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PrincipledDescription {
+    base_color: ColorSource,
+    metallic: ScalarSource,
+    roughness: ScalarSource,
+    specular: ScalarSource,
+    clearcoat: ScalarSource,
+    clearcoat_roughness: f64,
+    sheen: ScalarSource,
+    transmission: ScalarSource,
+    ior: f64,
+    emission: ColorSource,
+}
+
+impl TintDescription {
+    fn absorption(&self, setting: &str) -> Result<Color, SceneError> {
+        match (&self.absorption, &self.color, self.color_distance) {
+            (None, None, None) => Ok(Color::new(0.0, 0.0, 0.0)),
+            (Some(absorption), None, None) => to_color(setting, absorption),
+            (None, Some(color), distance) => {
+                let distance = distance.unwrap_or(1.0);
+                if !is_positive(distance) {
+                    return Err(invalid(format!(
+                        "{setting}: color_distance must be positive, got {distance}"
+                    )));
+                }
+                Ok(absorption_from_color(to_color(setting, color)?, distance))
+            }
+            _ => Err(invalid(format!(
+                "{setting}: give either absorption, or color with an optional color_distance"
+            ))),
+        }
+    }
+}
+
+impl ThinFilmDescription {
+    fn thin_film(&self, setting: &str) -> Result<ThinFilm, SceneError> {
+        if !is_non_negative(self.thickness) {
+            return Err(invalid(format!(
+                "{setting}: thin_film thickness must not be negative, got {}",
+                self.thickness
+            )));
+        }
+        if !is_positive(self.refraction_index) {
+            return Err(invalid(format!(
+                "{setting}: thin_film refraction_index must be positive, got {}",
+                self.refraction_index
+            )));
+        }
+        Ok(ThinFilm::new(self.thickness, self.refraction_index))
+    }
+}
+
+impl IndexDescription {
+    fn refractive_index(&self, setting: &str) -> Result<RefractiveIndex, SceneError> {
+        let index = match self {
+            IndexDescription::Constant(n) => {
+                if !is_positive(*n) {
+                    return Err(invalid(format!(
+                        "{setting}: refraction_index must be positive, got {n}"
+                    )));
+                }
+                return Ok(RefractiveIndex::Constant(*n));
+            }
+            IndexDescription::Dispersive(DispersionDescription::Cauchy { a, b }) => {
+                RefractiveIndex::Cauchy { a: *a, b: *b }
+            }
+            IndexDescription::Dispersive(DispersionDescription::Sellmeier { b, c }) => {
+                RefractiveIndex::Sellmeier { b: *b, c: *c }
+            }
+        };
+        // A varying index must make sense at every wavelength a spectral render may trace.
+        let mut wavelength = MIN_WAVELENGTH;
+        while wavelength <= MAX_WAVELENGTH {
+            let n = index.at(wavelength);
+            if !is_positive(n) {
+                return Err(invalid(format!(
+                    "{setting}: refraction_index must be positive, got {n} at {wavelength} nm"
+                )));
+            }
+            wavelength += 10.0;
+        }
+        Ok(index)
+    }
+}
+
+impl Default for PrincipledDescription {
+    fn default() -> Self {
+        PrincipledDescription {
+            base_color: ColorSource::Color([0.8, 0.8, 0.8]),
+            metallic: ScalarSource::Value(0.0),
+            roughness: ScalarSource::Value(0.5),
+            specular: ScalarSource::Value(0.5),
+            clearcoat: ScalarSource::Value(0.0),
+            clearcoat_roughness: 0.1,
+            sheen: ScalarSource::Value(0.0),
+            transmission: ScalarSource::Value(0.0),
+            ior: 1.5,
+            emission: ColorSource::Color([0.0, 0.0, 0.0]),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PresetDescription {
+    Gold,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+    Silver,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        // Where the center has moved to at time 1, for a sphere in motion.
+        end_center: Option<[f64; 3]>,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: String,
+    },
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Disk {
+        center: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Box {
+        a: [f64; 3],
+        b: [f64; 3],
+        material: String,
+    },
+    Mesh {
+        file: PathBuf,
+        // Used for faces that don't reference a material of their own.
+        material: Option<String>,
+    },
+    Instance {
+        object: Box<ObjectDescription>,
+        #[serde(default)]
+        translate: [f64; 3],
+        // Angles in degrees about the x, y and z axes.
+        #[serde(default)]
+        rotate: [f64; 3],
+        #[serde(default)]
+        scale: ScaleDescription,
+    },
+    ConstantMedium {
+        // A closed, convex object filled by the medium.
+        boundary: Box<ObjectDescription>,
+        density: f64,
+        // Usually an isotropic material.
+        material: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDescription {
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+    },
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        intensity: [f64; 3],
+        // Degrees from the axis within which the light is at full intensity, and beyond which
+        // it is dark.
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+    Directional {
+        // The direction the light travels in.
+        direction: [f64; 3],
+        irradiance: [f64; 3],
+        // In degrees, about 0.5 for the sun.
+        #[serde(default)]
+        angular_diameter: f64,
+    },
+}
+
+// Either the same scale factor on every axis, or one per axis.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDescription {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+impl Default for ScaleDescription {
+    fn default() -> Self {
+        ScaleDescription::Uniform(1.0)
+    }
+}
+
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    parse_scene(&source, path)
+}
+
+fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    // Builds a scene from the contents of the scene file at the given path.
+    let description: SceneDescription =
+        toml::from_str(source).map_err(|e| SceneError::Parse(path.to_path_buf(), e))?;
+    // Files referenced by the scene are relative to the scene file itself.
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    let camera = description.camera(base_dir)?;
+    let mut textures = Textures::new(&description.textures, base_dir);
+    let materials = description.materials(&mut textures)?;
+
+    let mut objects = Objects::new(&materials, base_dir);
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+    for (index, object) in description.objects.iter().enumerate() {
+        let mut built = HittableList::new();
+        objects.build(index, object, &mut built)?;
+        for hittable in built.objects {
+            if description.is_light(object) {
+                lights.add(hittable.clone());
+            }
+            world.add(hittable);
+        }
+    }
+
+    let analytic_lights = description.analytic_lights()?;
+
+    Ok(Scene {
+        camera,
+        world,
+        lights,
+        analytic_lights,
+    })
+}
+
+impl SceneDescription {
+    fn is_light(&self, object: &ObjectDescription) -> bool {
+        // Whether the object emits light and can be sampled as a light source. Moving spheres
+        // can't, and neither can meshes or volumes.
+        let emissive = |name: &String| match self.materials.get(name) {
+            Some(MaterialDescription::DiffuseLight { .. }) => true,
+            Some(MaterialDescription::Principled(principled)) => !matches!(
+                principled.emission,
+                ColorSource::Color([r, g, b]) if r == 0.0 && g == 0.0 && b == 0.0
+            ),
+            _ => false,
+        };
+        match object {
+            ObjectDescription::Sphere {
+                end_center,
+                material,
+                ..
+            } => end_center.is_none() && emissive(material),
+            ObjectDescription::Triangle { material, .. }
+            | ObjectDescription::Quad { material, .. }
+            | ObjectDescription::Disk { material, .. }
+            | ObjectDescription::Box { material, .. } => emissive(material),
+            ObjectDescription::Instance { object, .. } => self.is_light(object),
+            ObjectDescription::Mesh { .. } | ObjectDescription::ConstantMedium { .. } => false,
+        }
+    }
+
+    fn camera(&self, base_dir: &Path) -> Result<Camera, SceneError> {
+        let render = &self.render;
+        let camera = &self.camera;
+        let background = match &camera.background {
+            BackgroundDescription::Named(name) if name == "sky" => Background::Sky,
+            BackgroundDescription::Named(name) => {
+                return Err(invalid(format!(
+                    "camera.background must be \"sky\", a color or a table, got \"{name}\""
+                )))
+            }
+            BackgroundDescription::Solid(color) => {
+                Background::Solid(to_color("camera.background", color)?)
+            }
+            BackgroundDescription::Environment(environment) => {
+                if !is_non_negative(environment.intensity) {
+                    return Err(invalid("camera.background.intensity must not be negative"));
+                }
+                if !environment.rotation.is_finite() {
+                    return Err(invalid("camera.background.rotation must be finite"));
+                }
+                let path = base_dir.join(&environment.environment);
+                let mut map = EnvironmentMap::open(&path).map_err(|e| {
+                    invalid(format!(
+                        "camera.background: failed to load `{}`: {e}",
+                        path.display()
+                    ))
+                })?;
+                map.intensity = environment.intensity;
+                map.rotation = environment.rotation;
+                Background::Environment(Arc::new(map))
+            }
+        };
+
+        let mut camera = Camera::new(
+            render.aspect_ratio,
+            render.image_width,
+            render.samples_per_pixel,
+            render.max_depth,
+            camera.vfov,
+            to_vec3(&camera.lookfrom),
+            to_vec3(&camera.lookat),
+            to_vec3(&camera.vup),
+            camera.defocus_angle,
+            camera.focus_dist,
+        );
+        camera.background = background;
+        camera.shutter_open = self.camera.shutter_open;
+        camera.shutter_close = self.camera.shutter_close;
+        camera.mis = render.mis;
+        camera.min_bounces = render.min_bounces;
+        camera.spectral = render.spectral;
+        camera.validate().map_err(invalid)?;
+        Ok(camera)
+    }
+
+    fn materials(
+        &self,
+        textures: &mut Textures,
+    ) -> Result<HashMap<String, Arc<dyn Scatter>>, SceneError> {
+        // Build every texture up front, so that broken ones are reported even when unused.
+        for name in self.textures.keys() {
+            textures.get(name, &format!("texture `{name}`"))?;
+        }
+
+        let mut materials: HashMap<String, Arc<dyn Scatter>> = HashMap::new();
+        for (name, material) in &self.materials {
+            let setting = format!("material `{name}`");
+            let material: Arc<dyn Scatter> = match material {
+                MaterialDescription::Lambertian { albedo } => {
+                    Arc::new(Lambertian::from_texture(textures.source(&setting, albedo)?))
+                }
+                MaterialDescription::Metal {
+                    albedo,
+                    fuzz,
+                    thin_film,
+                } => {
+                    if !(0.0..=1.0).contains(fuzz) {
+                        return Err(invalid(format!(
+                            "material `{name}`: fuzz must be between 0 and 1, got {fuzz}"
+                        )));
+                    }
+                    let albedo = textures.source(&setting, albedo)?;
+                    match thin_film {
+                        Some(film) => Arc::new(Metal::with_thin_film(
+                            albedo,
+                            *fuzz,
+                            film.thin_film(&setting)?,
+                        )),
+                        None => Arc::new(Metal::from_texture(albedo, *fuzz)),
+                    }
+                }
+                MaterialDescription::Dielectric {
+                    refraction_index,
+                    thin_film,
+                    tint,
+                } => {
+                    let refraction_index = refraction_index.refractive_index(&setting)?;
+                    let absorption = tint.absorption(&setting)?;
+                    match thin_film {
+                        Some(film) => Arc::new(Dielectric::with_thin_film(
+                            refraction_index,
+                            absorption,
+                            film.thin_film(&setting)?,
+                        )),
+                        None => Arc::new(Dielectric::with_dispersion(refraction_index, absorption)),
+                    }
+                }
+                MaterialDescription::RoughDielectric {
+                    refraction_index,
+                    roughness,
+                    tint,
+                } => {
+                    if !is_positive(*refraction_index) {
+                        return Err(invalid(format!(
+                            "material `{name}`: refraction_index must be positive, got {refraction_index}"
+                        )));
+                    }
+                    if !(0.0..=1.0).contains(roughness) {
+                        return Err(invalid(format!(
+                            "material `{name}`: roughness must be between 0 and 1, got {roughness}"
+                        )));
+                    }
+                    Arc::new(RoughDielectric::with_absorption(
+                        *refraction_index,
+                        *roughness,
+                        tint.absorption(&setting)?,
+                    ))
+                }
+                MaterialDescription::DiffuseLight { emit } => {
+                    Arc::new(DiffuseLight::from_texture(textures.source(&setting, emit)?))
+                }
+                MaterialDescription::Isotropic { albedo } => {
+                    Arc::new(Isotropic::from_texture(textures.source(&setting, albedo)?))
+                }
+                MaterialDescription::Conductor {
+                    preset,
+                    eta,
+                    k,
+                    roughness,
+                    anisotropy,
+                } => {
+                    let (eta, k) = match (preset, eta, k) {
+                        (Some(preset), None, None) => match preset {
+                            PresetDescription::Gold => MetalPreset::Gold,
+                            PresetDescription::Copper => MetalPreset::Copper,
+                            PresetDescription::Aluminium => MetalPreset::Aluminium,
+                            PresetDescription::Silver => MetalPreset::Silver,
+                        }
+                        .ior(),
+                        (None, Some(eta), Some(k)) => {
+                            (to_color(&setting, eta)?, to_color(&setting, k)?)
+                        }
+                        _ => {
+                            return Err(invalid(format!(
+                                "{setting}: give either a preset, or both eta and k"
+                            )))
+                        }
+                    };
+                    if !(0.0..=1.0).contains(roughness) {
+                        return Err(invalid(format!(
+                            "{setting}: roughness must be between 0 and 1, got {roughness}"
+                        )));
+                    }
+                    if !(0.0..1.0).contains(anisotropy) {
+                        return Err(invalid(format!(
+                            "{setting}: anisotropy must be at least 0 and below 1, got {anisotropy}"
+                        )));
+                    }
+                    Arc::new(Conductor::with_anisotropy(eta, k, *roughness, *anisotropy))
+                }
+                MaterialDescription::Principled(principled) => {
+                    if !is_positive(principled.ior) {
+                        return Err(invalid(format!(
+                            "{setting}: ior must be positive, got {}",
+                            principled.ior
+                        )));
+                    }
+                    if !(0.0..=1.0).contains(&principled.clearcoat_roughness) {
+                        return Err(invalid(format!(
+                            "{setting}: clearcoat_roughness must be between 0 and 1"
+                        )));
+                    }
+                    let mut material = Principled::from_texture(
+                        textures.source(&setting, &principled.base_color)?,
+                    );
+                    material.metallic = textures.scalar(&setting, &principled.metallic)?;
+                    material.roughness = textures.scalar(&setting, &principled.roughness)?;
+                    material.specular = textures.scalar(&setting, &principled.specular)?;
+                    material.clearcoat = textures.scalar(&setting, &principled.clearcoat)?;
+                    material.clearcoat_roughness = principled.clearcoat_roughness;
+                    material.sheen = textures.scalar(&setting, &principled.sheen)?;
+                    material.transmission = textures.scalar(&setting, &principled.transmission)?;
+                    material.ior = principled.ior;
+                    material.emission = textures.source(&setting, &principled.emission)?;
+                    Arc::new(material)
+                }
+            };
+            materials.insert(name.clone(), material);
+        }
+        Ok(materials)
+    }
+
+    fn analytic_lights(&self) -> Result<Vec<Arc<dyn Light>>, SceneError> {
+        let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+        for (index, light) in self.lights.iter().enumerate() {
+            let setting = format!("light {index}");
+            let light: Arc<dyn Light> = match light {
+                LightDescription::Point {
+                    position,
+                    intensity,
+                } => Arc::new(PointLight::new(
+                    to_vec3(position),
+                    to_color(&setting, intensity)?,
+                )),
+                LightDescription::Spot {
+                    position,
+                    direction,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                } => {
+                    check_direction(&setting, direction)?;
+                    if !(0.0..=180.0).contains(inner_angle)
+                        || !(*inner_angle..=180.0).contains(outer_angle)
+                    {
+                        return Err(invalid(format!(
+                            "{setting}: angles must satisfy 0 <= inner_angle <= outer_angle <= 180"
+                        )));
+                    }
+                    Arc::new(SpotLight::new(
+                        to_vec3(position),
+                        to_vec3(direction),
+                        to_color(&setting, intensity)?,
+                        *inner_angle,
+                        *outer_angle,
+                    ))
+                }
+                LightDescription::Directional {
+                    direction,
+                    irradiance,
+                    angular_diameter,
+                } => {
+                    check_direction(&setting, direction)?;
+                    if !(0.0..180.0).contains(angular_diameter) {
+                        return Err(invalid(format!(
+                            "{setting}: angular_diameter must be at least 0 and below 180 degrees"
+                        )));
+                    }
+                    Arc::new(DirectionalLight::new(
+                        to_vec3(direction),
+                        to_color(&setting, irradiance)?,
+                        *angular_diameter,
+                    ))
+                }
+            };
+            lights.push(light);
+        }
+        Ok(lights)
+    }
+}
+
+// Builds the textures of a scene on demand, following references between them.
+struct Textures<'a> {
+    descriptions: &'a HashMap<String, TextureDescription>,
+    base_dir: &'a Path,
+    built: HashMap<String, Arc<dyn Texture>>,
+    // Names of the textures currently being built, to detect reference cycles.
+    building: Vec<String>,
+}
+
+impl<'a> Textures<'a> {
+    fn new(descriptions: &'a HashMap<String, TextureDescription>, base_dir: &'a Path) -> Self {
+        Textures {
+            descriptions,
+            base_dir,
+            built: HashMap::new(),
+            building: Vec::new(),
+        }
+    }
+
+    fn source(
+        &mut self,
+        setting: &str,
+        source: &ColorSource,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        match source {
+            ColorSource::Color(color) => Ok(Arc::new(SolidColor::new(to_color(setting, color)?))),
+            ColorSource::Texture(name) => self.get(name, setting),
+        }
+    }
+
+    fn scalar(
+        &mut self,
+        setting: &str,
+        source: &ScalarSource,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        match source {
+            ScalarSource::Value(value) => {
+                if !(0.0..=1.0).contains(value) {
+                    return Err(invalid(format!(
+                        "{setting}: values must be between 0 and 1, got {value}"
+                    )));
+                }
+                Ok(Arc::new(SolidColor::new(Color::new(
+                    *value, *value, *value,
+                ))))
+            }
+            ScalarSource::Texture(name) => self.get(name, setting),
+        }
+    }
+
+    fn get(&mut self, name: &str, setting: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(texture) = self.built.get(name) {
+            return Ok(texture.clone());
+        }
+        let description =
+            self.descriptions
+                .get(name)
+                .ok_or_else(|| SceneError::UnknownTexture {
+                    setting: setting.to_string(),
+                    name: name.to_string(),
+                })?;
+        if self.building.iter().any(|n| n == name) {
+            return Err(invalid(format!(
+                "texture `{name}` is part of a reference cycle"
+            )));
+        }
+
+        self.building.push(name.to_string());
+        let setting = format!("texture `{name}`");
+        let texture: Arc<dyn Texture> = match description {
+            TextureDescription::Solid { color } => {
+                Arc::new(SolidColor::new(to_color(&setting, color)?))
+            }
+            TextureDescription::Checker { scale, even, odd } => {
+                if !is_positive(*scale) {
+                    return Err(invalid(format!(
+                        "{setting}: scale must be positive, got {scale}"
+                    )));
+                }
+                Arc::new(CheckerTexture::new(
+                    *scale,
+                    self.source(&setting, even)?,
+                    self.source(&setting, odd)?,
+                ))
+            }
+            TextureDescription::Image { file, linear } => {
+                let path = self.base_dir.join(file);
+                let image = if *linear {
+                    ImageTexture::open_linear(&path)
+                } else {
+                    ImageTexture::open(&path)
+                };
+                Arc::new(image.map_err(|e| {
+                    invalid(format!(
+                        "{setting}: failed to load `{}`: {e}",
+                        path.display()
+                    ))
+                })?)
+            }
+            TextureDescription::Noise { scale } => {
+                if !scale.is_finite() {
+                    return Err(invalid(format!(
+                        "{setting}: scale must be finite, got {scale}"
+                    )));
+                }
+                Arc::new(NoiseTexture::new(*scale))
+            }
+        };
+        self.building.pop();
+
+        self.built.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+}
+
+// Builds the objects of a scene, sharing the meshes loaded from the same file between them.
+struct Objects<'a> {
+    materials: &'a HashMap<String, Arc<dyn Scatter>>,
+    base_dir: &'a Path,
+    // Meshes already loaded, by file and default material.
+    meshes: HashMap<MeshKey, Vec<Arc<dyn Hittable>>>,
+}
+
+type MeshKey = (PathBuf, Option<String>);
+
+impl<'a> Objects<'a> {
+    fn new(materials: &'a HashMap<String, Arc<dyn Scatter>>, base_dir: &'a Path) -> Self {
+        Objects {
+            materials,
+            base_dir,
+            meshes: HashMap::new(),
+        }
+    }
+
+    fn build(
+        &mut self,
+        index: usize,
+        object: &ObjectDescription,
+        world: &mut HittableList,
+    ) -> Result<(), SceneError> {
+        let material = |name: &String| {
+            self.materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| SceneError::UnknownMaterial {
+                    object: index,
+                    name: name.clone(),
+                })
+        };
+
+        match object {
+            ObjectDescription::Sphere {
+                center,
+                end_center,
+                radius,
+                material: name,
+            } => {
+                if !is_positive(*radius) {
+                    return Err(invalid(format!(
+                        "object {index}: sphere radius must be positive, got {radius}"
+                    )));
+                }
+                let sphere = match end_center {
+                    Some(end_center) => Sphere::new_moving(
+                        to_vec3(center),
+                        to_vec3(end_center),
+                        *radius,
+                        material(name)?,
+                    ),
+                    None => Sphere::new(to_vec3(center), *radius, material(name)?),
+                };
+                world.add(Arc::new(sphere));
+            }
+            ObjectDescription::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material: name,
+            } => {
+                world.add(Arc::new(Triangle::with_vertex_data(
+                    vertices.map(|v| to_vec3(&v)),
+                    normals.map(|normals| normals.map(|n| to_vec3(&n))),
+                    *uvs,
+                    material(name)?,
+                )));
+            }
+            ObjectDescription::Quad {
+                q,
+                u,
+                v,
+                material: name,
+            } => {
+                check_edges(index, u, v)?;
+                world.add(Arc::new(Quad::new(
+                    to_vec3(q),
+                    to_vec3(u),
+                    to_vec3(v),
+                    material(name)?,
+                )));
+            }
+            ObjectDescription::Disk {
+                center,
+                u,
+                v,
+                material: name,
+            } => {
+                check_edges(index, u, v)?;
+                world.add(Arc::new(Disk::new(
+                    to_vec3(center),
+                    to_vec3(u),
+                    to_vec3(v),
+                    material(name)?,
+                )));
+            }
+            ObjectDescription::Box {
+                a,
+                b,
+                material: name,
+            } => {
+                world.add(Arc::new(make_box(to_vec3(a), to_vec3(b), material(name)?)));
+            }
+            ObjectDescription::Mesh {
+                file,
+                material: name,
+            } => {
+                let path = self.base_dir.join(file);
+                let key = (path.clone(), name.clone());
+                if !self.meshes.contains_key(&key) {
+                    let default_material = match name {
+                        Some(name) => material(name)?,
+                        None => Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+                    };
+                    let meshes = obj::load_obj(path, default_material).map_err(SceneError::Obj)?;
+                    self.meshes.insert(key.clone(), meshes.objects);
+                }
+                for mesh in &self.meshes[&key] {
+                    world.add(mesh.clone());
+                }
+            }
+            ObjectDescription::Instance {
+                object,
+                translate,
+                rotate,
+                scale,
+            } => {
+                let scale = match scale {
+                    ScaleDescription::Uniform(s) => [*s; 3],
+                    ScaleDescription::PerAxis(s) => *s,
+                };
+                if !translate
+                    .iter()
+                    .chain(rotate)
+                    .chain(&scale)
+                    .all(|x| x.is_finite())
+                {
+                    return Err(invalid(format!(
+                        "object {index}: instance scale, rotate and translate must be finite"
+                    )));
+                }
+
+                let instanced = self.build_single(index, object)?;
+
+                // Scale first, then rotate about the x, y and z axes in turn, then translate.
+                let matrix = Mat4::translation(to_vec3(translate))
+                    * Mat4::rotation_z(rotate[2])
+                    * Mat4::rotation_y(rotate[1])
+                    * Mat4::rotation_x(rotate[0])
+                    * Mat4::scaling(to_vec3(&scale));
+                let transform = Transform::new(instanced, matrix).ok_or_else(|| {
+                    invalid(format!(
+                        "object {index}: instance transform can't be inverted, as its scale is \
+                         zero or too small"
+                    ))
+                })?;
+                world.add(Arc::new(transform));
+            }
+            ObjectDescription::ConstantMedium {
+                boundary,
+                density,
+                material: name,
+            } => {
+                if !is_positive(*density) {
+                    return Err(invalid(format!(
+                        "object {index}: medium density must be positive, got {density}"
+                    )));
+                }
+                let phase_function = material(name)?;
+                let boundary = self.build_single(index, boundary)?;
+                world.add(Arc::new(ConstantMedium::with_phase_function(
+                    boundary,
+                    *density,
+                    phase_function,
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn build_single(
+        &mut self,
+        index: usize,
+        object: &ObjectDescription,
+    ) -> Result<Arc<dyn Hittable>, SceneError> {
+        // Builds an object that is part of another one, as a single hittable.
+        let mut parts = HittableList::new();
+        self.build(index, object, &mut parts)?;
+        Ok(if parts.objects.len() == 1 {
+            parts.objects.pop().unwrap()
+        } else {
+            Arc::new(BvhNode::new(parts))
+        })
+    }
+}
+
+fn check_edges(object: usize, u: &[f64; 3], v: &[f64; 3]) -> Result<(), SceneError> {
+    // The area is compared with the lengths of the edges, so that small shapes are accepted
+    // as long as their edges point in different directions.
+    let (u, v) = (to_vec3(u), to_vec3(v));
+    let area = u.cross(v).length();
+    if !(area.is_finite() && area > 1e-12 * u.length() * v.length()) {
+        return Err(invalid(format!(
+            "object {object}: edges u and v must not be parallel or zero"
+        )));
+    }
+    Ok(())
+}
+
+fn check_direction(setting: &str, direction: &[f64; 3]) -> Result<(), SceneError> {
+    if !direction.iter().all(|x| x.is_finite()) || to_vec3(direction).near_zero() {
+        return Err(invalid(format!(
+            "{setting}: direction must be finite and not zero"
+        )));
+    }
+    Ok(())
+}
+
+fn invalid(message: impl Into<String>) -> SceneError {
+    SceneError::Invalid(message.into())
+}
+
+// TOML allows `nan` and `inf`, which these reject along with values out of range.
+fn is_positive(x: f64) -> bool {
+    x.is_finite() && x > 0.0
+}
+
+fn is_non_negative(x: f64) -> bool {
+    x.is_finite() && x >= 0.0
+}
+
+fn to_vec3(v: &[f64; 3]) -> Vec3 {
+    Point3::new(v[0], v[1], v[2])
+}
+
+fn to_color(setting: &str, c: &[f64; 3]) -> Result<Color, SceneError> {
+    if !c.iter().all(|&x| is_non_negative(x)) {
+        return Err(invalid(format!(
+            "{setting}: color components must be finite and not negative"
+        )));
+    }
+    Ok(Color::new(c[0], c[1], c[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPHERE: &str = r#"
+[materials.gray]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = "gray"
+"#;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(source, Path::new("test.toml"))
+    }
+
+    fn error(source: &str) -> String {
+        match parse(source) {
+            Ok(_) => panic!("the scene should be rejected:\n{source}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn assert_errors(cases: &[(String, &str)]) {
+        for (source, expected) in cases {
+            assert_eq!(&error(source), expected, "for the scene\n{source}");
+        }
+    }
+
+    #[test]
+    fn valid_scene() {
+        let scene = parse(SPHERE).unwrap();
+        assert_eq!(scene.world.objects.len(), 1);
+    }
+
+    #[test]
+    fn unknown_material() {
+        let source = SPHERE.replace("material = \"gray\"", "material = \"grey\"");
+        assert!(matches!(
+            parse(&source),
+            Err(SceneError::UnknownMaterial { object: 0, ref name }) if name == "grey"
+        ));
+        assert_eq!(error(&source), "object 0 uses unknown material `grey`");
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let message = error(&SPHERE.replace("radius = 0.5", "radius = 0.5\nradios = 0.5"));
+        assert!(message.starts_with("test.toml: "), "{message}");
+        assert!(message.contains("unknown field `radios`"), "{message}");
+
+        let message = error(&format!("[render]\nsamples = 10\n{SPHERE}"));
+        assert!(message.contains("unknown field `samples`"), "{message}");
+    }
+
+    #[test]
+    fn out_of_range_settings() {
+        assert_errors(&[
+            (
+                SPHERE.replace("radius = 0.5", "radius = -0.5"),
+                "object 0: sphere radius must be positive, got -0.5",
+            ),
+            (
+                format!("[render]\naspect_ratio = 0.0\n{SPHERE}"),
+                "aspect_ratio must be positive",
+            ),
+            (
+                format!("[render]\nimage_width = 0\n{SPHERE}"),
+                "image_width must be at least 1",
+            ),
+            (
+                format!("[camera]\nvfov = 180.0\n{SPHERE}"),
+                "vfov must be between 0 and 180 degrees, got 180",
+            ),
+            (
+                format!("[camera]\nlookfrom = [1.0, 2.0, 3.0]\nlookat = [1.0, 2.0, 3.0]\n{SPHERE}"),
+                "lookfrom and lookat must differ",
+            ),
+            (
+                format!("[camera]\nfocus_dist = 0.0\n{SPHERE}"),
+                "focus_dist must be positive",
+            ),
+            (
+                format!("[camera]\nlookfrom = [0.0, 3.0, 0.0]\nlookat = [0.0, 0.0, 0.0]\n{SPHERE}"),
+                "vup must not be parallel to the view direction",
+            ),
+            (
+                SPHERE.replace(
+                    "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                    "type = \"metal\"\nalbedo = [0.5, 0.5, 0.5]\nfuzz = 1.5",
+                ),
+                "material `gray`: fuzz must be between 0 and 1, got 1.5",
+            ),
+            (
+                SPHERE.replace("albedo = [0.5, 0.5, 0.5]", "albedo = [0.5, -0.5, 0.5]"),
+                "material `gray`: color components must be finite and not negative",
+            ),
+            (
+                SPHERE.replace(
+                    "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                    "type = \"dielectric\"\nrefraction_index = 0.0",
+                ),
+                "material `gray`: refraction_index must be positive, got 0",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn nan_and_infinity_are_rejected() {
+        // TOML has literals for both, which slip through comparisons such as `x <= 0.0`.
+        assert_errors(&[
+            (
+                SPHERE.replace("radius = 0.5", "radius = nan"),
+                "object 0: sphere radius must be positive, got NaN",
+            ),
+            (
+                SPHERE.replace("radius = 0.5", "radius = inf"),
+                "object 0: sphere radius must be positive, got inf",
+            ),
+            (
+                format!("[render]\naspect_ratio = nan\n{SPHERE}"),
+                "aspect_ratio must be positive",
+            ),
+            (
+                format!("[camera]\nvfov = nan\n{SPHERE}"),
+                "vfov must be between 0 and 180 degrees, got NaN",
+            ),
+            (
+                format!("[camera]\nlookat = [0.0, nan, -1.0]\n{SPHERE}"),
+                "lookfrom, lookat and vup must be finite",
+            ),
+            (
+                format!("[camera]\ndefocus_angle = nan\n{SPHERE}"),
+                "defocus_angle must not be negative",
+            ),
+            (
+                format!("[camera]\nfocus_dist = -nan\n{SPHERE}"),
+                "focus_dist must be positive",
+            ),
+            (
+                SPHERE.replace("albedo = [0.5, 0.5, 0.5]", "albedo = [0.5, nan, 0.5]"),
+                "material `gray`: color components must be finite and not negative",
+            ),
+            (
+                SPHERE.replace(
+                    "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                    "type = \"metal\"\nalbedo = [0.5, 0.5, 0.5]\nfuzz = nan",
+                ),
+                "material `gray`: fuzz must be between 0 and 1, got NaN",
+            ),
+            (
+                SPHERE.replace(
+                    "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                    "type = \"dielectric\"\nrefraction_index = nan",
+                ),
+                "material `gray`: refraction_index must be positive, got NaN",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn textures() {
+        let source = SPHERE.replace(
+            "albedo = [0.5, 0.5, 0.5]",
+            "albedo = \"checker\"\n\n[textures.checker]\ntype = \"checker\"\nscale = 0.5\neven = \"white\"\nodd = [0.0, 0.0, 0.0]\n\n[textures.white]\ntype = \"solid\"\ncolor = [1.0, 1.0, 1.0]",
+        );
+        assert!(parse(&source).is_ok());
+
+        assert!(matches!(
+            parse(&source.replace("even = \"white\"", "even = \"whit\"")),
+            Err(SceneError::UnknownTexture { ref name, .. }) if name == "whit"
+        ));
+        assert_errors(&[
+            (
+                source.replace("color = [1.0, 1.0, 1.0]", "color = [1.0, inf, 1.0]"),
+                "texture `white`: color components must be finite and not negative",
+            ),
+            (
+                source.replace("scale = 0.5", "scale = 0.0"),
+                "texture `checker`: scale must be positive, got 0",
+            ),
+            (
+                source.replace("scale = 0.5", "scale = nan"),
+                "texture `checker`: scale must be positive, got NaN",
+            ),
+            (
+                source.replace(
+                    "type = \"solid\"\ncolor = [1.0, 1.0, 1.0]",
+                    "type = \"noise\"\nscale = inf",
+                ),
+                "texture `white`: scale must be finite, got inf",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn texture_cycles_are_rejected() {
+        let source = SPHERE.replace(
+            "albedo = [0.5, 0.5, 0.5]",
+            "albedo = \"a\"\n\n[textures.a]\ntype = \"checker\"\nscale = 1.0\neven = \"b\"\nodd = [0.0, 0.0, 0.0]\n\n[textures.b]\ntype = \"checker\"\nscale = 1.0\neven = [1.0, 1.0, 1.0]\nodd = \"a\"",
+        );
+        let message = error(&source);
+        assert!(
+            message.ends_with("is part of a reference cycle"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn quad_edges() {
+        let quad = |u: &str, v: &str| {
+            format!("{SPHERE}\n[[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, -2.0]\nu = {u}\nv = {v}\nmaterial = \"gray\"\n")
+        };
+        // Small quads are fine as long as their edges aren't parallel.
+        let scene = parse(&quad("[1e-7, 0.0, 0.0]", "[0.0, 1e-7, 0.0]")).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        let message = "object 1: edges u and v must not be parallel or zero";
+        assert_errors(&[
+            (quad("[1e-7, 0.0, 0.0]", "[2e-7, 0.0, 0.0]"), message),
+            (quad("[1.0, 0.0, 0.0]", "[0.0, 0.0, 0.0]"), message),
+            (quad("[nan, 0.0, 0.0]", "[0.0, 1.0, 0.0]"), message),
+            (quad("[inf, 0.0, 0.0]", "[0.0, 1.0, 0.0]"), message),
+        ]);
+    }
+
+    #[test]
+    fn instances() {
+        let instance = |settings: &str| {
+            format!(
+                "{SPHERE}\n[[objects]]\ntype = \"instance\"\n{settings}\nobject = {{ type = \
+                 \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"gray\" }}\n"
+            )
+        };
+        let scene = parse(&instance("scale = 1e-13\ntranslate = [1e10, 0.0, 0.0]")).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        let flat =
+            "object 1: instance transform can't be inverted, as its scale is zero or too small";
+        assert_errors(&[
+            (instance("scale = 0.0"), flat),
+            (instance("scale = [1.0, 1e-13, 1.0]"), flat),
+            (
+                instance("rotate = [0.0, nan, 0.0]"),
+                "object 1: instance scale, rotate and translate must be finite",
+            ),
+            (
+                instance("translate = [0.0, 0.0, -inf]"),
+                "object 1: instance scale, rotate and translate must be finite",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn shutter() {
+        assert_errors(&[
+            (
+                format!("[camera]\nshutter_open = 0.5\nshutter_close = 0.25\n{SPHERE}"),
+                "shutter_close must not come before shutter_open",
+            ),
+            (
+                format!("[camera]\nshutter_close = nan\n{SPHERE}"),
+                "shutter_open and shutter_close must be finite",
+            ),
+        ]);
+        let source = format!("[camera]\nshutter_open = 0.5\nshutter_close = 0.5\n{SPHERE}");
+        assert_eq!(parse(&source).unwrap().camera.shutter_close, 0.5);
+    }
+
+    #[test]
+    fn medium_density() {
+        let medium = |density: &str| {
+            format!(
+                "{SPHERE}\n[[objects]]\ntype = \"constant_medium\"\ndensity = {density}\n\
+                 material = \"gray\"\nboundary = {{ type = \"sphere\", center = [0.0, 0.0, 0.0], \
+                 radius = 1.0, material = \"gray\" }}\n"
+            )
+        };
+        assert_eq!(parse(&medium("0.5")).unwrap().world.objects.len(), 2);
+        assert_errors(&[
+            (
+                medium("0.0"),
+                "object 1: medium density must be positive, got 0",
+            ),
+            (
+                medium("nan"),
+                "object 1: medium density must be positive, got NaN",
+            ),
+            (
+                medium("inf"),
+                "object 1: medium density must be positive, got inf",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn emissive_objects_are_lights() {
+        let source = format!(
+            "{SPHERE}\n[materials.lamp]\ntype = \"diffuse_light\"\nemit = [4.0, 4.0, 4.0]\n\n\
+             [[objects]]\ntype = \"quad\"\nq = [0.0, 2.0, 0.0]\nu = [1.0, 0.0, 0.0]\n\
+             v = [0.0, 0.0, 1.0]\nmaterial = \"lamp\"\n\n\
+             [[objects]]\ntype = \"instance\"\ntranslate = [0.0, 1.0, 0.0]\n\
+             object = {{ type = \"box\", a = [0.0, 0.0, 0.0], b = [1.0, 1.0, 1.0], \
+             material = \"lamp\" }}\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 3.0, 0.0]\nradius = 0.5\n\
+             end_center = [1.0, 3.0, 0.0]\nmaterial = \"lamp\"\n"
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        // The gray sphere doesn't emit, and moving spheres can't be sampled.
+        assert_eq!(scene.lights.objects.len(), 2);
+    }
+
+    #[test]
+    fn environment_background() {
+        let background = |table: &str| format!("[camera]\nbackground = {table}\n{SPHERE}");
+        let message = error(&background("{ environment = \"missing.hdr\" }"));
+        assert!(
+            message.starts_with("camera.background: failed to load `missing.hdr`"),
+            "{message}"
+        );
+        assert_errors(&[
+            (
+                background("{ environment = \"sky.hdr\", intensity = -1.0 }"),
+                "camera.background.intensity must not be negative",
+            ),
+            (
+                background("{ environment = \"sky.hdr\", intensity = nan }"),
+                "camera.background.intensity must not be negative",
+            ),
+            (
+                background("{ environment = \"sky.hdr\", rotation = inf }"),
+                "camera.background.rotation must be finite",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn analytic_lights() {
+        let light = |fields: &str| format!("{SPHERE}\n[[lights]]\n{fields}\n");
+        let scene = parse(&light(
+            "type = \"point\"\nposition = [0.0, 2.0, 0.0]\nintensity = [1.0, 1.0, 1.0]\n\n\
+             [[lights]]\ntype = \"directional\"\ndirection = [0.0, -1.0, 0.0]\n\
+             irradiance = [2.0, 2.0, 2.0]",
+        ))
+        .unwrap();
+        assert_eq!(scene.analytic_lights.len(), 2);
+
+        let spot = |direction: &str, angles: &str| {
+            light(&format!(
+                "type = \"spot\"\nposition = [0.0, 2.0, 0.0]\ndirection = {direction}\n\
+                 intensity = [1.0, 1.0, 1.0]\n{angles}"
+            ))
+        };
+        let sun = |direction: &str, angular_diameter: &str| {
+            light(&format!(
+                "type = \"directional\"\ndirection = {direction}\nirradiance = [1.0, 1.0, 1.0]\n\
+                 angular_diameter = {angular_diameter}"
+            ))
+        };
+        let angles = "inner_angle = 20.0\nouter_angle = 30.0";
+        assert_errors(&[
+            (
+                spot("[0.0, 0.0, 0.0]", angles),
+                "light 0: direction must be finite and not zero",
+            ),
+            (
+                spot("[0.0, nan, 0.0]", angles),
+                "light 0: direction must be finite and not zero",
+            ),
+            (
+                spot("[0.0, -1.0, 0.0]", "inner_angle = 30.0\nouter_angle = 20.0"),
+                "light 0: angles must satisfy 0 <= inner_angle <= outer_angle <= 180",
+            ),
+            (
+                spot("[0.0, -1.0, 0.0]", "inner_angle = nan\nouter_angle = 20.0"),
+                "light 0: angles must satisfy 0 <= inner_angle <= outer_angle <= 180",
+            ),
+            (
+                sun("[0.0, -1.0, inf]", "0.5"),
+                "light 0: direction must be finite and not zero",
+            ),
+            (
+                sun("[0.0, -1.0, 0.0]", "180.0"),
+                "light 0: angular_diameter must be at least 0 and below 180 degrees",
+            ),
+            (
+                sun("[0.0, -1.0, 0.0]", "nan"),
+                "light 0: angular_diameter must be at least 0 and below 180 degrees",
+            ),
+            (
+                light("type = \"point\"\nposition = [0.0, 2.0, 0.0]\nintensity = [1.0, nan, 1.0]"),
+                "light 0: color components must be finite and not negative",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn conductor() {
+        let conductor = |fields: &str| {
+            SPHERE.replace(
+                "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                &format!("type = \"conductor\"\n{fields}"),
+            )
+        };
+        parse(&conductor("preset = \"gold\"\nroughness = 0.3")).unwrap();
+        parse(&conductor(
+            "eta = [0.2, 0.9, 1.1]\nk = [3.9, 2.4, 2.2]\nroughness = 0.5\nanisotropy = 0.8",
+        ))
+        .unwrap();
+        assert_errors(&[
+            (
+                conductor("preset = \"gold\"\neta = [0.2, 0.9, 1.1]"),
+                "material `gray`: give either a preset, or both eta and k",
+            ),
+            (
+                conductor("eta = [0.2, 0.9, 1.1]"),
+                "material `gray`: give either a preset, or both eta and k",
+            ),
+            (
+                conductor("eta = [0.2, 0.9, 1.1]\nk = [3.9, nan, 2.2]"),
+                "material `gray`: color components must be finite and not negative",
+            ),
+            (
+                conductor("preset = \"silver\"\nroughness = 1.5"),
+                "material `gray`: roughness must be between 0 and 1, got 1.5",
+            ),
+            (
+                conductor("preset = \"silver\"\nroughness = nan"),
+                "material `gray`: roughness must be between 0 and 1, got NaN",
+            ),
+            (
+                conductor("preset = \"copper\"\nanisotropy = 1.0"),
+                "material `gray`: anisotropy must be at least 0 and below 1, got 1",
+            ),
+            (
+                conductor("preset = \"copper\"\nanisotropy = nan"),
+                "material `gray`: anisotropy must be at least 0 and below 1, got NaN",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn principled() {
+        let principled = |fields: &str| {
+            SPHERE.replace(
+                "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                &format!("type = \"principled\"\n{fields}"),
+            )
+        };
+        parse(&principled("")).unwrap();
+        let textured = format!(
+            "[textures.rust]\ntype = \"noise\"\nscale = 4.0\n{}",
+            principled("base_color = [0.7, 0.3, 0.1]\nmetallic = \"rust\"\nroughness = 0.3")
+        );
+        parse(&textured).unwrap();
+        assert_errors(&[
+            (
+                principled("roughness = 2.0"),
+                "material `gray`: values must be between 0 and 1, got 2",
+            ),
+            (
+                principled("sheen = nan"),
+                "material `gray`: values must be between 0 and 1, got NaN",
+            ),
+            (
+                principled("ior = 0.0"),
+                "material `gray`: ior must be positive, got 0",
+            ),
+            (
+                principled("ior = inf"),
+                "material `gray`: ior must be positive, got inf",
+            ),
+            (
+                principled("clearcoat_roughness = nan"),
+                "material `gray`: clearcoat_roughness must be between 0 and 1",
+            ),
+            (
+                principled("metallic = \"missing\""),
+                "material `gray` uses unknown texture `missing`",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn rough_dielectric() {
+        let glass = |fields: &str| {
+            SPHERE.replace(
+                "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                &format!("type = \"rough_dielectric\"\n{fields}"),
+            )
+        };
+        parse(&glass("refraction_index = 1.5\nroughness = 0.3")).unwrap();
+        assert_errors(&[
+            (
+                glass("refraction_index = nan\nroughness = 0.3"),
+                "material `gray`: refraction_index must be positive, got NaN",
+            ),
+            (
+                glass("refraction_index = -1.5\nroughness = 0.3"),
+                "material `gray`: refraction_index must be positive, got -1.5",
+            ),
+            (
+                glass("refraction_index = 1.5\nroughness = inf"),
+                "material `gray`: roughness must be between 0 and 1, got inf",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn glass_tint() {
+        let glass = |fields: &str| {
+            SPHERE.replace(
+                "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                &format!("type = \"dielectric\"\nrefraction_index = 1.5\n{fields}"),
+            )
+        };
+        parse(&glass("absorption = [0.1, 0.2, 0.3]")).unwrap();
+        parse(&glass("color = [0.9, 0.5, 0.0]\ncolor_distance = 2.0")).unwrap();
+        parse(&glass("color = [0.9, 0.5, 0.2]")).unwrap();
+        assert_errors(&[
+            (
+                glass("absorption = [0.1, 0.2, 0.3]\ncolor = [0.9, 0.5, 0.2]"),
+                "material `gray`: give either absorption, or color with an optional \
+                 color_distance",
+            ),
+            (
+                glass("color_distance = 2.0"),
+                "material `gray`: give either absorption, or color with an optional \
+                 color_distance",
+            ),
+            (
+                glass("absorption = [0.1, nan, 0.3]"),
+                "material `gray`: color components must be finite and not negative",
+            ),
+            (
+                glass("color = [0.9, 0.5, 0.2]\ncolor_distance = 0.0"),
+                "material `gray`: color_distance must be positive, got 0",
+            ),
+            (
+                glass("color = [0.9, 0.5, 0.2]\ncolor_distance = nan"),
+                "material `gray`: color_distance must be positive, got NaN",
+            ),
+        ]);
+
+        // Fields that are neither the material's nor the tint's are still rejected.
+        let message = error(&glass("bogus = 1"));
+        assert!(message.contains("bogus"), "{message}");
+    }
+
+    #[test]
+    fn dispersive_refraction_index() {
+        let glass = |index: &str| {
+            SPHERE.replace(
+                "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                &format!("type = \"dielectric\"\nrefraction_index = {index}"),
+            )
+        };
+        parse(&glass("{ cauchy = { a = 1.5046, b = 0.0042 } }")).unwrap();
+        parse(&glass(
+            "{ sellmeier = { b = [1.03961212, 0.231792344, 1.01046945], \
+             c = [0.00600069867, 0.0200179144, 103.560653] } }",
+        ))
+        .unwrap();
+        let message = error(&glass("{ cauchy = { a = 0.1, b = -0.1 } }"));
+        assert!(
+            message.starts_with("material `gray`: refraction_index must be positive, got -"),
+            "{message}"
+        );
+        assert_errors(&[
+            (
+                glass("nan"),
+                "material `gray`: refraction_index must be positive, got NaN",
+            ),
+            (
+                glass("{ cauchy = { a = nan, b = 0.0042 } }"),
+                "material `gray`: refraction_index must be positive, got NaN at 360 nm",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn thin_film() {
+        let coated = |material: &str, film: &str| {
+            SPHERE.replace(
+                "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+                &format!("{material}\nthin_film = {{ {film} }}"),
+            )
+        };
+        let glass = "type = \"dielectric\"\nrefraction_index = 1.0";
+        let metal = "type = \"metal\"\nalbedo = [0.9, 0.6, 0.3]\nfuzz = 0.1";
+        parse(&coated(glass, "thickness = 400.0, refraction_index = 1.33")).unwrap();
+        parse(&coated(metal, "thickness = 250.0, refraction_index = 1.5")).unwrap();
+        assert_errors(&[
+            (
+                coated(glass, "thickness = -1.0, refraction_index = 1.33"),
+                "material `gray`: thin_film thickness must not be negative, got -1",
+            ),
+            (
+                coated(metal, "thickness = nan, refraction_index = 1.33"),
+                "material `gray`: thin_film thickness must not be negative, got NaN",
+            ),
+            (
+                coated(glass, "thickness = 400.0, refraction_index = 0.0"),
+                "material `gray`: thin_film refraction_index must be positive, got 0",
+            ),
+            (
+                coated(metal, "thickness = 400.0, refraction_index = nan"),
+                "material `gray`: thin_film refraction_index must be positive, got NaN",
+            ),
+        ]);
+    }
+}
@@ -0,0 +1,483 @@
+use core::f64;
+use std::sync::Arc;
+
+use crate::{
+    camera::{Background, Camera},
+    constant_medium::ConstantMedium,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    material::*,
+    matrix::Mat4,
+    quad::{make_box, Quad},
+    random::{random_double, random_double_range},
+    scene::Scene,
+    sphere::Sphere,
+    texture::{CheckerTexture, NoiseTexture},
+    transform::Transform,
+    vec3::{Color, Point3, Vec3},
+};
+
+// The final scene of "Ray Tracing in One Weekend": a field of small random spheres around three
+// large ones.
+pub fn random_spheres() -> Scene {
+    spheres_field(false)
+}
+
+// The same scene as in "Ray Tracing: The Next Week", where the small diffuse spheres bounce up
+// while the shutter is open.
+pub fn bouncing_spheres() -> Scene {
+    spheres_field(true)
+}
+
+fn spheres_field(bouncing: bool) -> Scene {
+    // World
+    let mut world: HittableList = HittableList::new();
+
+    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_material,
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_double();
+            let center = Point3::new(
+                a as f64 + 0.9 * random_double(),
+                0.2,
+                b as f64 + 0.9 * random_double(),
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random() * Color::random();
+                    let sphere_material = Arc::new(Lambertian::new(albedo));
+                    if bouncing {
+                        let center2 = center + Vec3::new(0.0, random_double_range(0.0, 0.5), 0.0);
+                        world.add(Arc::new(Sphere::new_moving(
+                            center,
+                            center2,
+                            0.2,
+                            sphere_material,
+                        )));
+                    } else {
+                        world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+                    }
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_in_range(0.5, 1.0);
+                    let fuzz = random_double_range(0.0, 0.5);
+                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
+                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+                } else {
+                    // glass
+                    let sphere_material = Arc::new(Dielectric::new(1.5));
+                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+                }
+            }
+        }
+    }
+
+    let material1 = Arc::new(Dielectric::new(1.5));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 1200;
+    let samples_per_pixel = 500;
+    let max_depth = 50;
+    let vfov = 20.0;
+    let lookfrom = Point3::new(13.0, 2.0, 3.0);
+    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let defocus_angle = 0.6;
+    let focus_dist = 10.0;
+
+    let camera = Camera::new(
+        aspect_ratio,
+        image_width,
+        samples_per_pixel,
+        max_depth,
+        vfov,
+        lookfrom,
+        lookat,
+        vup,
+        defocus_angle,
+        focus_dist,
+    );
+
+    Scene {
+        camera,
+        world,
+        lights: HittableList::new(),
+        analytic_lights: Vec::new(),
+    }
+}
+
+// Two large checkered spheres, one above the other.
+pub fn checkered_spheres() -> Scene {
+    let mut world = HittableList::new();
+
+    let checker = Arc::new(CheckerTexture::from_colors(
+        0.32,
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -10.0, 0.0),
+        10.0,
+        Arc::new(Lambertian::from_texture(checker.clone())),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 10.0, 0.0),
+        10.0,
+        Arc::new(Lambertian::from_texture(checker)),
+    )));
+
+    let camera = Camera::new(
+        16.0 / 9.0,
+        400,
+        100,
+        50,
+        20.0,
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+
+    Scene {
+        camera,
+        world,
+        lights: HittableList::new(),
+        analytic_lights: Vec::new(),
+    }
+}
+
+// A marble sphere resting on a marble ground.
+pub fn perlin_spheres() -> Scene {
+    let mut world = HittableList::new();
+
+    let pertext = Arc::new(NoiseTexture::new(4.0));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_texture(pertext.clone())),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Arc::new(Lambertian::from_texture(pertext)),
+    )));
+
+    let camera = Camera::new(
+        16.0 / 9.0,
+        400,
+        100,
+        50,
+        20.0,
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+
+    Scene {
+        camera,
+        world,
+        lights: HittableList::new(),
+        analytic_lights: Vec::new(),
+    }
+}
+
+// Five colored quads facing the camera from different sides.
+pub fn quads() -> Scene {
+    let mut world = HittableList::new();
+
+    // Materials
+    let left_red = Arc::new(Lambertian::new(Color::new(1.0, 0.2, 0.2)));
+    let back_green = Arc::new(Lambertian::new(Color::new(0.2, 1.0, 0.2)));
+    let right_blue = Arc::new(Lambertian::new(Color::new(0.2, 0.2, 1.0)));
+    let upper_orange = Arc::new(Lambertian::new(Color::new(1.0, 0.5, 0.0)));
+    let lower_teal = Arc::new(Lambertian::new(Color::new(0.2, 0.8, 0.8)));
+
+    // Quads
+    world.add(Arc::new(Quad::new(
+        Point3::new(-3.0, -2.0, 5.0),
+        Vec3::new(0.0, 0.0, -4.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        left_red,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-2.0, -2.0, 0.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        back_green,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(3.0, -2.0, 1.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        right_blue,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-2.0, 3.0, 1.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        upper_orange,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-2.0, -3.0, 5.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -4.0),
+        lower_teal,
+    )));
+
+    let camera = Camera::new(
+        1.0,
+        400,
+        100,
+        50,
+        80.0,
+        Point3::new(0.0, 0.0, 9.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+
+    Scene {
+        camera,
+        world,
+        lights: HittableList::new(),
+        analytic_lights: Vec::new(),
+    }
+}
+
+// The marble spheres, lit only by a spherical and a rectangular light.
+pub fn simple_light() -> Scene {
+    let mut world = HittableList::new();
+
+    let pertext = Arc::new(NoiseTexture::new(4.0));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_texture(pertext.clone())),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Arc::new(Lambertian::from_texture(pertext)),
+    )));
+
+    let difflight = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+    let mut lights = HittableList::new();
+    lights.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 7.0, 0.0),
+        2.0,
+        difflight.clone(),
+    )));
+    lights.add(Arc::new(Quad::new(
+        Point3::new(3.0, 1.0, -2.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        difflight,
+    )));
+    for light in &lights.objects {
+        world.add(light.clone());
+    }
+
+    let mut camera = Camera::new(
+        16.0 / 9.0,
+        400,
+        100,
+        50,
+        20.0,
+        Point3::new(26.0, 3.0, 6.0),
+        Point3::new(0.0, 2.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+    camera.background = Background::Solid(Color::new(0.0, 0.0, 0.0));
+
+    Scene {
+        camera,
+        world,
+        lights,
+        analytic_lights: Vec::new(),
+    }
+}
+
+// The Cornell box: a room with red and green side walls, lit by a light in the ceiling, holding
+// two white blocks.
+pub fn cornell_box() -> Scene {
+    // The light faces down into the room.
+    let light_material = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+    let light = Arc::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light_material,
+    ));
+    let mut world = cornell_room(light.clone());
+    let mut lights = HittableList::new();
+    lights.add(light);
+
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let (tall_block, short_block) = cornell_blocks(white);
+    world.add(tall_block);
+    world.add(short_block);
+
+    Scene {
+        camera: cornell_camera(),
+        world,
+        lights,
+        analytic_lights: Vec::new(),
+    }
+}
+
+// The Cornell box with its blocks made of smoke, under a larger and dimmer light.
+pub fn cornell_smoke() -> Scene {
+    let light_material = Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0)));
+    let light = Arc::new(Quad::new(
+        Point3::new(113.0, 554.0, 127.0),
+        Vec3::new(330.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 305.0),
+        light_material,
+    ));
+    let mut world = cornell_room(light.clone());
+    let mut lights = HittableList::new();
+    lights.add(light);
+
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let (tall_block, short_block) = cornell_blocks(white);
+    world.add(Arc::new(ConstantMedium::new(
+        tall_block,
+        0.01,
+        Color::new(0.0, 0.0, 0.0),
+    )));
+    world.add(Arc::new(ConstantMedium::new(
+        short_block,
+        0.01,
+        Color::new(1.0, 1.0, 1.0),
+    )));
+
+    Scene {
+        camera: cornell_camera(),
+        world,
+        lights,
+        analytic_lights: Vec::new(),
+    }
+}
+
+// The walls of the Cornell box around the given ceiling light.
+fn cornell_room(light: Arc<Quad>) -> HittableList {
+    let mut world = HittableList::new();
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    world.add(Arc::new(Quad::new(
+        Point3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        green,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        red,
+    )));
+    world.add(light);
+    world.add(Arc::new(Quad::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(555.0, 555.0, 555.0),
+        Vec3::new(-555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -555.0),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(0.0, 0.0, 555.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        white,
+    )));
+
+    world
+}
+
+// The tall and the short block of the Cornell box.
+fn cornell_blocks(mat: Arc<dyn Scatter>) -> (Arc<dyn Hittable>, Arc<dyn Hittable>) {
+    // The blocks are built at the origin, then turned and moved into place.
+    let tall_block = Arc::new(make_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 330.0, 165.0),
+        mat.clone(),
+    ));
+    let short_block = Arc::new(make_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 165.0, 165.0),
+        mat,
+    ));
+
+    (
+        Arc::new(
+            Transform::new(
+                tall_block,
+                Mat4::translation(Vec3::new(265.0, 0.0, 295.0)) * Mat4::rotation_y(15.0),
+            )
+            .expect("rotations are invertible"),
+        ),
+        Arc::new(
+            Transform::new(
+                short_block,
+                Mat4::translation(Vec3::new(130.0, 0.0, 65.0)) * Mat4::rotation_y(-18.0),
+            )
+            .expect("rotations are invertible"),
+        ),
+    )
+}
+
+fn cornell_camera() -> Camera {
+    let mut camera = Camera::new(
+        1.0,
+        600,
+        200,
+        50,
+        40.0,
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+    );
+    camera.background = Background::Solid(Color::new(0.0, 0.0, 0.0));
+    camera
+}
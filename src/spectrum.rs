@@ -0,0 +1,296 @@
+use std::sync::OnceLock;
+
+use crate::{
+    random::random_double,
+    vec3::{Color, Vec3},
+};
+
+// Wavelengths are in nanometres, over the range of the color matching functions.
+pub const MIN_WAVELENGTH: f64 = 360.0;
+pub const MAX_WAVELENGTH: f64 = 830.0;
+// The yellow helium line, at which glasses quote their refractive index.
+pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+// How the refractive index of a dielectric varies with the wavelength, which spreads white
+// light into its colors.
+#[derive(Clone, Copy, Debug)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    // Cauchy's equation n = a + b / λ², with the wavelength λ in micrometres.
+    Cauchy { a: f64, b: f64 },
+    // The Sellmeier equation n² = 1 + Σ b λ² / (λ² - c), with λ in micrometres, as given in
+    // glass catalogues.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match self {
+            RefractiveIndex::Constant(n) => *n,
+            RefractiveIndex::Cauchy { a, b } => a + b / l2,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+}
+
+// Draws a wavelength with a density that follows the eye's sensitivity, so that few samples
+// are spent where they barely change the color. This is the distribution of pbrt's visible
+// wavelength sampling.
+pub fn sample_wavelength() -> f64 {
+    wavelength_at(random_double())
+}
+
+fn wavelength_at(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn wavelength_fraction(wavelength: f64) -> f64 {
+    // The inverse of `wavelength_at`.
+    (0.85691062 - ((538.0 - wavelength) / 138.888889).tanh()) / 1.82750197
+}
+
+fn wavelength_pdf(wavelength: f64) -> f64 {
+    if !(MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&wavelength) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (wavelength - 538.0)).cosh().powi(2)
+}
+
+// The wavelengths a spectral path carries in the three channels of its colors: a hero
+// wavelength, which decides the path where materials split wavelengths apart, in the first,
+// and two more spread evenly through the distribution of wavelengths from it.
+#[derive(Clone, Copy)]
+pub struct Wavelengths {
+    wavelengths: [f64; 3],
+    pdfs: [f64; 3],
+    // How much of the red, green and blue bands of an upsampled color each wavelength sees.
+    bands: [Vec3; 3],
+}
+
+impl Wavelengths {
+    pub fn from_hero(hero: f64) -> Self {
+        let u = wavelength_fraction(hero);
+        let wavelengths = [
+            hero,
+            wavelength_at((u + 1.0 / 3.0).fract()),
+            wavelength_at((u + 2.0 / 3.0).fract()),
+        ];
+        Wavelengths {
+            wavelengths,
+            pdfs: wavelengths.map(wavelength_pdf),
+            bands: wavelengths.map(bands),
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.wavelengths[0]
+    }
+
+    pub fn upsample(&self, rgb: Color) -> Color {
+        // The values at the three wavelengths of a smooth spectrum of the given linear color.
+        Color::new(
+            self.bands[0].dot(rgb),
+            self.bands[1].dot(rgb),
+            self.bands[2].dot(rgb),
+        )
+    }
+
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        // Estimates the linear sRGB color of a spectrum from its radiance at the three
+        // wavelengths, through the CIE XYZ color matching functions. Single estimates are often
+        // outside the sRGB gamut, with negative components, and are left so: clamping them
+        // would bias their average, so only the finished pixels are clipped to the gamut.
+        let mut xyz = Vec3::default();
+        for i in 0..3 {
+            if self.pdfs[i] > 0.0 {
+                xyz += radiance[i] / self.pdfs[i] * color_matching(self.wavelengths[i]);
+            }
+        }
+        white_balance(xyz_to_linear_srgb(xyz / 3.0))
+    }
+}
+
+pub fn upsample_at(rgb: Color, wavelength: f64) -> f64 {
+    // The value at one wavelength of the spectrum a linear color is upsampled to.
+    bands(wavelength).dot(rgb)
+}
+
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    // The linear color of a surface with the given reflectance at each wavelength, under light
+    // that is white. Wavelengths 10 nm apart are fine enough for smooth spectra.
+    static WEIGHTS: OnceLock<Vec<(f64, Color)>> = OnceLock::new();
+    let weights = WEIGHTS.get_or_init(|| {
+        let steps = 47;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f64;
+        (0..steps)
+            .map(|i| {
+                let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+                let rgb = xyz_to_linear_srgb(step * color_matching(wavelength));
+                (wavelength, white_balance(rgb))
+            })
+            .collect()
+    });
+    weights
+        .iter()
+        .fold(Color::default(), |rgb, &(wavelength, weight)| {
+            rgb + reflectance(wavelength) * weight
+        })
+}
+
+fn bands(wavelength: f64) -> Vec3 {
+    // Upsamples colors as red, green and blue bands that blend into each other. They sum to one
+    // at every wavelength, so white stays a flat spectrum and colors between 0 and 1 stay valid
+    // reflectances, and their edges are placed so that colors come back out nearly unchanged.
+    let smoothstep = |edge: f64| {
+        let t = ((wavelength - edge + 30.0) / 60.0).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let (green_up, red_up) = (smoothstep(488.0), smoothstep(588.0));
+    Vec3::new(red_up, green_up - red_up, 1.0 - green_up)
+}
+
+fn color_matching(wavelength: f64) -> Vec3 {
+    // The CIE 1931 standard observer, by the multi-lobe Gaussian fit of Wyman, Sloan and
+    // Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (2013).
+    let g = |mu: f64, below: f64, above: f64| {
+        let t = (wavelength - mu) / if wavelength < mu { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
+}
+
+fn white_balance(rgb: Color) -> Color {
+    // Divides by the color of a flat spectrum, so that it comes out white, as light of the color
+    // white does in RGB renders.
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = *WHITE.get_or_init(|| {
+        // Integrates the color matching functions by the midpoint rule.
+        let steps = 4700;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f64;
+        let mut xyz = Vec3::default();
+        for i in 0..steps {
+            xyz += step * color_matching(MIN_WAVELENGTH + (i as f64 + 0.5) * step);
+        }
+        xyz_to_linear_srgb(xyz)
+    });
+    Color::new(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Averages the color estimates of a spectrum over hero wavelengths spread evenly through
+    // their distribution, which converges far faster than drawing them at random.
+    pub(crate) fn average_rgb(spectrum: impl Fn(&Wavelengths) -> Color) -> Color {
+        let n = 10_000;
+        let mut sum = Color::default();
+        for i in 0..n {
+            let wavelengths = Wavelengths::from_hero(wavelength_at((i as f64 + 0.5) / n as f64));
+            sum += wavelengths.to_rgb(spectrum(&wavelengths));
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn a_flat_spectrum_is_white() {
+        let white = average_rgb(|_| Color::new(1.0, 1.0, 1.0));
+        assert!(
+            (white - Color::new(1.0, 1.0, 1.0)).length() < 1e-3,
+            "{white:?}"
+        );
+        let gray = average_rgb(|_| Color::new(0.25, 0.25, 0.25));
+        assert!(
+            (gray - Color::new(0.25, 0.25, 0.25)).length() < 1e-3,
+            "{gray:?}"
+        );
+    }
+
+    #[test]
+    fn upsampled_colors_come_back_nearly_unchanged() {
+        for color in [
+            Color::new(0.8, 0.2, 0.1),
+            Color::new(0.1, 0.6, 0.2),
+            Color::new(0.2, 0.3, 0.9),
+            Color::new(0.5, 0.5, 0.5),
+        ] {
+            let rgb = average_rgb(|wavelengths| wavelengths.upsample(color));
+            assert!(
+                (rgb - color).length() < 0.1,
+                "{color:?} came back as {rgb:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn bands_sum_to_one() {
+        let mut wavelength = MIN_WAVELENGTH;
+        while wavelength <= MAX_WAVELENGTH {
+            let b = bands(wavelength);
+            assert!((b.x() + b.y() + b.z() - 1.0).abs() < 1e-12);
+            assert!(b.x() >= 0.0 && b.y() >= 0.0 && b.z() >= 0.0);
+            wavelength += 5.0;
+        }
+    }
+
+    #[test]
+    fn wavelength_sampling_matches_its_density() {
+        for u in [0.01, 0.25, 0.5, 0.75, 0.99] {
+            let wavelength = wavelength_at(u);
+            assert!((MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&wavelength));
+            assert!((wavelength_fraction(wavelength) - u).abs() < 1e-9);
+            // The density is the derivative of the fraction of samples below a wavelength.
+            let h = 1e-3;
+            let derivative = (wavelength_fraction(wavelength + h)
+                - wavelength_fraction(wavelength - h))
+                / (2.0 * h);
+            assert!((wavelength_pdf(wavelength) - derivative).abs() < 1e-6 * derivative.max(1.0));
+        }
+        assert_eq!(wavelength_pdf(MIN_WAVELENGTH - 1.0), 0.0);
+    }
+
+    #[test]
+    fn refractive_index_of_bk7() {
+        // Schott's Sellmeier coefficients for N-BK7, and a Cauchy fit to it.
+        let sellmeier = RefractiveIndex::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        assert!((sellmeier.at(REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-4);
+        let cauchy = RefractiveIndex::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        assert!((cauchy.at(REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-3);
+        // Blue light is bent more than red.
+        for index in [sellmeier, cauchy] {
+            assert!(index.is_dispersive());
+            assert!(index.at(450.0) > index.at(650.0));
+        }
+        assert_eq!(RefractiveIndex::Constant(1.5).at(450.0), 1.5);
+    }
+}
@@ -1,32 +1,91 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
+    aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
     material::*,
+    onb::Onb,
+    random::random_double,
     ray::Ray,
     vec3::{Point3, Vec3},
 };
 
 pub struct Sphere {
-    center: Point3,
+    // The center at time 0, moving along the ray direction to its position at time 1.
+    center: Ray,
     radius: f64,
     mat: Arc<dyn Scatter>,
+    bbox: Aabb,
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, mat: Arc<dyn Scatter>) -> Self {
+        let radius = f64::max(0.0, radius);
+        let rvec = Vec3::new(radius, radius, radius);
         Sphere {
-            center,
-            radius: f64::max(0.0, radius),
+            center: Ray::new(center, Vec3::new(0.0, 0.0, 0.0)),
+            radius,
             mat,
+            bbox: Aabb::from_points(center - rvec, center + rvec),
         }
     }
+
+    pub fn new_moving(
+        center1: Point3,
+        center2: Point3,
+        radius: f64,
+        mat: Arc<dyn Scatter>,
+    ) -> Self {
+        // A sphere that moves from center1 at time 0 to center2 at time 1, and rests at either
+        // end outside of that range.
+        let radius = f64::max(0.0, radius);
+        let rvec = Vec3::new(radius, radius, radius);
+        let box1 = Aabb::from_points(center1 - rvec, center1 + rvec);
+        let box2 = Aabb::from_points(center2 - rvec, center2 + rvec);
+        Sphere {
+            center: Ray::new(center1, center2 - center1),
+            radius,
+            mat,
+            bbox: Aabb::from_boxes(&box1, &box2),
+        }
+    }
+}
+
+impl Sphere {
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        // p: a given point on the sphere of radius one, centered at the origin.
+        // u: returned value [0,1] of angle around the Y axis from X=-1.
+        // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+        //     <1 0 0> yields <0.50 0.50>       <-1  0  0> yields <0.00 0.50>
+        //     <0 1 0> yields <0.50 1.00>       < 0 -1  0> yields <0.50 0.00>
+        //     <0 0 1> yields <0.25 0.50>       < 0  0 -1> yields <0.75 0.50>
+
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+        // Returns a direction around +z towards a sphere of the given radius and squared
+        // distance, uniformly distributed over the solid angle it covers.
+        let r1 = random_double();
+        let r2 = random_double();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Vec3::new(x, y, z)
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let oc: Vec3 = self.center - r.origin();
+        let current_center = self.center.at(r.time().clamp(0.0, 1.0));
+        let oc: Vec3 = current_center - r.origin();
         let dir = r.direction();
         let a = dir.length_squared();
         let h = dir.dot(oc);
@@ -50,10 +109,147 @@ impl Hittable for Sphere {
 
         rec.t = root;
         rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center) / self.radius;
+        let outward_normal = (rec.p - current_center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(&outward_normal);
         rec.mat = self.mat.clone();
 
         true
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        // This method only works for stationary spheres.
+        let mut rec = HitRecord::default();
+        if !self.hit(
+            &Ray::new(origin, direction),
+            Interval::from_values(0.001, f64::INFINITY),
+            &mut rec,
+        ) {
+            return 0.0;
+        }
+
+        // Seen from inside, the sphere covers every direction.
+        let distance_squared = (self.center.at(0.0) - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        // Returns a direction within the cone of directions that hit the sphere.
+        let direction = self.center.at(0.0) - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector();
+        }
+
+        let uvw = Onb::new(direction);
+        uvw.transform(Self::random_to_sphere(self.radius, distance_squared))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pdf::{tests::check_pdf, HittablePdf},
+        vec3::Color,
+    };
+
+    fn sphere() -> Sphere {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, mat)
+    }
+
+    fn hit(sphere: &Sphere, r: &Ray) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        sphere
+            .hit(r, Interval::from_values(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn sphere_hit() {
+        let sphere = sphere();
+        let origin = Point3::default();
+        let rec = hit(&sphere, &Ray::new(origin, Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((rec.t - 1.5).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        // From inside, the far side is hit with the normal facing back at the ray.
+        let inside = Ray::new(Point3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = hit(&sphere, &inside).unwrap();
+        assert!((rec.t - 0.5).abs() < 1e-12);
+        assert!(!rec.front_face);
+
+        let above = Ray::new(origin, Vec3::new(0.0, 1.0, -1.0));
+        assert!(hit(&sphere, &above).is_none());
+        let behind = Ray::new(origin, Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit(&sphere, &behind).is_none());
+    }
+
+    #[test]
+    fn sphere_uv() {
+        let cases = [
+            (Vec3::new(1.0, 0.0, 0.0), (0.5, 0.5)),
+            (Vec3::new(-1.0, 0.0, 0.0), (0.0, 0.5)),
+            (Vec3::new(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (Vec3::new(0.0, -1.0, 0.0), (0.5, 0.0)),
+            (Vec3::new(0.0, 0.0, 1.0), (0.25, 0.5)),
+            (Vec3::new(0.0, 0.0, -1.0), (0.75, 0.5)),
+        ];
+        for (p, (u, v)) in cases {
+            let uv = Sphere::get_sphere_uv(&p);
+            assert!(
+                (uv.0 - u).abs() < 1e-12 && (uv.1 - v).abs() < 1e-12,
+                "{p:?}: {uv:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn moving_sphere_hit() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new_moving(
+            Point3::new(0.0, 0.0, -2.0),
+            Point3::new(2.0, 0.0, -2.0),
+            0.5,
+            mat,
+        );
+        let bbox = sphere.bounding_box();
+        assert!(bbox.x.min <= -0.5 && bbox.x.max >= 2.5);
+
+        // A ray down the middle of the path sees the sphere only halfway through the move,
+        // and the sphere rests at either end outside of the shutter interval.
+        let at = |time| Ray::with_time(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+        assert!(hit(&sphere, &at(0.0)).is_none());
+        let rec = hit(&sphere, &at(0.5)).unwrap();
+        assert!((rec.t - 1.5).abs() < 1e-12);
+        assert!(hit(&sphere, &at(1.0)).is_none());
+        let end =
+            |time| Ray::with_time(Point3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+        assert!(hit(&sphere, &end(2.0)).is_some());
+    }
+
+    #[test]
+    fn pdf_value_matches_random() {
+        let sphere = sphere();
+        for origin in [
+            Point3::new(0.0, 0.0, -1.0),
+            Point3::new(0.3, -0.2, -0.5),
+            Point3::new(0.1, 0.1, -2.0),
+        ] {
+            check_pdf(&HittablePdf::new(&sphere, origin));
+        }
+    }
 }
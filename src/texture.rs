@@ -0,0 +1,197 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    color::gamma_to_linear,
+    perlin::Perlin,
+    vec3::{Color, Point3},
+};
+
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        SolidColor { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+// A 3D checker pattern, alternating between two textures in cubes of the given scale.
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x_integer = (self.inv_scale * p.x()).floor() as i64;
+        let y_integer = (self.inv_scale * p.y()).floor() as i64;
+        let z_integer = (self.inv_scale * p.z()).floor() as i64;
+
+        let is_even = (x_integer + y_integer + z_integer) % 2 == 0;
+
+        if is_even {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// A texture looked up from an image by the surface's texture coordinates.
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    // Linear colors, converted from the image's gamma encoding when loaded, unless opened as
+    // linear data.
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        Self::load(path, gamma_to_linear)
+    }
+
+    pub fn open_linear(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        // For images of data rather than colors, such as roughness maps, which are stored
+        // without gamma encoding.
+        Self::load(path, |c| c)
+    }
+
+    fn load(path: impl AsRef<Path>, decode: fn(f64) -> f64) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_rgb8();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                Color::new(
+                    decode(p[0] as f64 / 255.0),
+                    decode(p[1] as f64 / 255.0),
+                    decode(p[2] as f64 / 255.0),
+                )
+            })
+            .collect();
+        Ok(ImageTexture {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // If we have no texture data, then return solid cyan as a debugging aid.
+        if self.pixels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        // Wrap input texture coordinates to [0,1] x [1,0], flipping v since images are stored
+        // from the top down.
+        let u = u - u.floor();
+        let v = 1.0 - (v - v.floor());
+
+        let i = ((u * self.width as f64) as u32).min(self.width - 1);
+        let j = ((v * self.height as f64) as u32).min(self.height - 1);
+        self.pixels[j as usize * self.width as usize + i as usize]
+    }
+}
+
+// Marble-like stripes perturbed by Perlin turbulence.
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> Self {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let phase = self.scale * p.z() + 10.0 * self.noise.turb(p, 7);
+        Color::new(0.5, 0.5, 0.5) * (1.0 + phase.sin())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_between_cubes() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        let checker = CheckerTexture::from_colors(0.5, white, black);
+        let at = |x, y, z| checker.value(0.0, 0.0, &Point3::new(x, y, z));
+        assert_eq!(at(0.1, 0.1, 0.1), white);
+        assert_eq!(at(0.6, 0.1, 0.1), black);
+        assert_eq!(at(0.6, 0.6, 0.1), white);
+        assert_eq!(at(-0.1, 0.1, 0.1), black);
+    }
+
+    #[test]
+    fn image_is_looked_up_from_the_top_down() {
+        // 2x2 pixels: red and green on the top row, blue and white on the bottom one.
+        let texture = ImageTexture {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+                Color::new(1.0, 1.0, 1.0),
+            ],
+        };
+        let p = Point3::default();
+        assert_eq!(texture.value(0.25, 0.75, &p), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(texture.value(0.75, 0.75, &p), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(texture.value(0.25, 0.25, &p), Color::new(0.0, 0.0, 1.0));
+        // Coordinates wrap around, and the edges stay inside the image.
+        assert_eq!(texture.value(1.75, -0.75, &p), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(texture.value(1.0, 1.0, &p), Color::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let noise = NoiseTexture::new(4.0);
+        for i in 0..100 {
+            let p = Point3::new(i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.73);
+            let c = noise.value(0.0, 0.0, &p);
+            assert!((0.0..=1.0).contains(&c.x()) && c.x() == c.y() && c.y() == c.z());
+        }
+    }
+}
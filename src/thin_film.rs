@@ -0,0 +1,345 @@
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use crate::{
+    spectrum::{reflectance_to_rgb, upsample_at, RefractiveIndex},
+    vec3::Color,
+};
+
+// A thin transparent layer on a surface, such as a soap film or an oil slick. Light reflected
+// off its top and off the surface below interferes, so how much is reflected depends on the
+// wavelength and the angle, which colors the surface.
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    // In nanometres. Films of a few hundred show the most vivid colors.
+    pub thickness: f64,
+    pub refraction_index: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, refraction_index: f64) -> Self {
+        ThinFilm {
+            thickness,
+            refraction_index,
+        }
+    }
+
+    pub fn reflectance(&self, cos_theta: f64, eta: f64, k: f64, wavelength: f64) -> f64 {
+        // The fraction of unpolarized light arriving from outside at the given angle that the
+        // film reflects off a surface with the complex refractive index eta + ik, summing the
+        // waves bouncing back and forth inside it by the Airy formula.
+        let sin2 = Complex::real(1.0 - cos_theta * cos_theta);
+        let n = [
+            Complex::real(1.0),
+            Complex::real(self.refraction_index),
+            Complex::new(eta, k),
+        ];
+        // The refractive index times the cosine of the angle in each layer.
+        let q = n.map(|n| (n * n - sin2).sqrt());
+
+        // The film delays light on its way down and back up by this phase.
+        let delay = (4.0 * PI * self.thickness / wavelength) * q[1];
+        let phase = delay.exp_i();
+
+        let s = |i: usize, j: usize| (q[i] - q[j]) / (q[i] + q[j]);
+        let p = |i: usize, j: usize| {
+            let (a, b) = (n[j] * n[j] * q[i], n[i] * n[i] * q[j]);
+            (a - b) / (a + b)
+        };
+        let airy = |r12: Complex, r23: Complex| {
+            let r = (r12 + r23 * phase) / (Complex::real(1.0) + r12 * r23 * phase);
+            r.norm_sqr().min(1.0)
+        };
+        0.5 * (airy(s(0, 1), s(1, 2)) + airy(p(0, 1), p(1, 2)))
+    }
+}
+
+// Cosines of the angle of incidence, and reflectances of the metal under a film, at which the
+// colors films reflect in RGB renders are tabulated. Summing the interference over the spectrum
+// at every hit would be slow, and the colors change smoothly enough with both for linear
+// interpolation.
+const COS_STEPS: usize = 64;
+const REFLECTANCE_STEPS: usize = 16;
+
+// A film on glass, which knows its reflectance for the hero wavelength of a spectral ray and,
+// tabulated over the angle, the color it gives white light in RGB renders.
+pub struct DielectricFilm {
+    film: ThinFilm,
+    refraction_index: RefractiveIndex,
+    rgb: Vec<Color>,
+}
+
+impl DielectricFilm {
+    pub fn new(film: ThinFilm, refraction_index: RefractiveIndex) -> Self {
+        let rgb = (0..=COS_STEPS)
+            .map(|i| {
+                let cos_theta = i as f64 / COS_STEPS as f64;
+                clip(reflectance_to_rgb(|wavelength| {
+                    film.reflectance(cos_theta, refraction_index.at(wavelength), 0.0, wavelength)
+                }))
+            })
+            .collect();
+        DielectricFilm {
+            film,
+            refraction_index,
+            rgb,
+        }
+    }
+
+    pub fn reflectance(&self, cos_theta: f64, wavelength: Option<f64>) -> Color {
+        match wavelength {
+            Some(wavelength) => {
+                let r = self.film.reflectance(
+                    cos_theta,
+                    self.refraction_index.at(wavelength),
+                    0.0,
+                    wavelength,
+                );
+                Color::new(r, r, r)
+            }
+            None => interpolate(&self.rgb, cos_theta),
+        }
+    }
+}
+
+// A film on metal. The metal's albedo can come from a texture, so its RGB table is over the
+// metal's reflectance as well as the angle, with one for each of the red, green and blue bands
+// that colors are upsampled to. Adding up the bands' shares is exact for gray metals and close
+// for others, as the bands only overlap where they blend into each other.
+pub struct ConductorFilm {
+    film: ThinFilm,
+    rgb: [Vec<Color>; 3],
+}
+
+impl ConductorFilm {
+    pub fn new(film: ThinFilm) -> Self {
+        let rgb = [0, 1, 2].map(|band| {
+            let mut unit = Color::default();
+            unit[band] = 1.0;
+            let mut table = Vec::with_capacity((REFLECTANCE_STEPS + 1) * (COS_STEPS + 1));
+            for j in 0..=REFLECTANCE_STEPS {
+                let (eta, k) = conductor_from_reflectance(j as f64 / REFLECTANCE_STEPS as f64);
+                for i in 0..=COS_STEPS {
+                    let cos_theta = i as f64 / COS_STEPS as f64;
+                    table.push(reflectance_to_rgb(|wavelength| {
+                        upsample_at(unit, wavelength)
+                            * film.reflectance(cos_theta, eta, k, wavelength)
+                    }));
+                }
+            }
+            table
+        });
+        ConductorFilm { film, rgb }
+    }
+
+    pub fn reflectance(&self, cos_theta: f64, albedo: Color, wavelength: Option<f64>) -> Color {
+        // The albedo is the metal's reflectance without the film.
+        match wavelength {
+            Some(wavelength) => {
+                let (eta, k) = conductor_from_reflectance(upsample_at(albedo, wavelength));
+                let r = self.film.reflectance(cos_theta, eta, k, wavelength);
+                Color::new(r, r, r)
+            }
+            None => {
+                let mut rgb = Color::default();
+                for (band, table) in self.rgb.iter().enumerate() {
+                    let x = albedo[band].clamp(0.0, 1.0) * REFLECTANCE_STEPS as f64;
+                    let j = (x as usize).min(REFLECTANCE_STEPS - 1);
+                    let t = x - j as f64;
+                    let row = |j: usize| &table[j * (COS_STEPS + 1)..(j + 1) * (COS_STEPS + 1)];
+                    rgb += (1.0 - t) * interpolate(row(j), cos_theta)
+                        + t * interpolate(row(j + 1), cos_theta);
+                }
+                clip(rgb)
+            }
+        }
+    }
+}
+
+fn interpolate(table: &[Color], cos_theta: f64) -> Color {
+    let x = cos_theta.clamp(0.0, 1.0) * COS_STEPS as f64;
+    let i = (x as usize).min(COS_STEPS - 1);
+    let t = x - i as f64;
+    (1.0 - t) * table[i] + t * table[i + 1]
+}
+
+fn clip(rgb: Color) -> Color {
+    // Interference colors can be too saturated for sRGB, and RGB renders clip them to valid
+    // reflectances. They come out paler than in spectral renders, which only clip the finished
+    // pixels.
+    Color::new(
+        rgb.x().clamp(0.0, 1.0),
+        rgb.y().clamp(0.0, 1.0),
+        rgb.z().clamp(0.0, 1.0),
+    )
+}
+
+fn conductor_from_reflectance(r: f64) -> (f64, f64) {
+    // A complex refractive index that reflects the given fraction of light head on, with a
+    // matching edge tint, by Gulbrandsen, "Artist Friendly Metallic Fresnel" (2014).
+    let r = r.clamp(0.0, 0.99);
+    let g = r;
+    let sqrt_r = r.sqrt();
+    let eta = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+    let k2 = (r * (eta + 1.0).powi(2) - (eta - 1.0).powi(2)) / (1.0 - r);
+    (eta, k2.max(0.0).sqrt())
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn sqrt(self) -> Self {
+        // The principal square root, whose real part isn't negative.
+        let r = self.norm_sqr().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp_i(self) -> Self {
+        // e to the power of i times self.
+        let scale = (-self.im).exp();
+        Complex::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Mul<Complex> for f64 {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(self * other.re, self * other.im)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let d = other.norm_sqr();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / d,
+            (self.im * other.re - self.re * other.im) / d,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{microfacet::fresnel_dielectric, spectrum::tests::average_rgb};
+
+    const GLASS: RefractiveIndex = RefractiveIndex::Constant(1.5);
+
+    fn assert_near(a: Color, b: Color, tolerance: f64) {
+        assert!((a - b).length() < tolerance, "{a:?} differs from {b:?}");
+    }
+
+    // The color a spectral render converges to, where the hero wavelength's light stands in
+    // for all three.
+    fn spectral_average(reflectance: impl Fn(f64) -> Color) -> Color {
+        average_rgb(|wavelengths| Color::new(3.0 * reflectance(wavelengths.hero()).x(), 0.0, 0.0))
+    }
+
+    #[test]
+    fn a_film_without_thickness_leaves_the_glass() {
+        let film = DielectricFilm::new(ThinFilm::new(0.0, 1.33), GLASS);
+        for cos_theta in [0.2, 0.5, 0.8, 1.0] {
+            let r = fresnel_dielectric(cos_theta, 1.5);
+            let spectral = film.reflectance(cos_theta, Some(550.0));
+            assert_near(spectral, Color::new(r, r, r), 1e-9);
+            let rgb = film.reflectance(cos_theta, None);
+            assert_near(rgb, Color::new(r, r, r), 1e-3);
+            let average =
+                spectral_average(|wavelength| film.reflectance(cos_theta, Some(wavelength)));
+            assert_near(rgb, average, 1e-3);
+        }
+    }
+
+    #[test]
+    fn a_film_matching_the_air_leaves_the_glass() {
+        let film = DielectricFilm::new(ThinFilm::new(300.0, 1.0), GLASS);
+        for cos_theta in [0.2, 0.5, 1.0] {
+            let r = fresnel_dielectric(cos_theta, 1.5);
+            let spectral = film.reflectance(cos_theta, Some(480.0));
+            assert_near(spectral, Color::new(r, r, r), 1e-9);
+        }
+    }
+
+    #[test]
+    fn rgb_films_match_spectral_films() {
+        // A thin film whose colors sRGB can show, so that RGB renders don't clip them.
+        let film = DielectricFilm::new(ThinFilm::new(100.0, 1.33), GLASS);
+        for cos_theta in [0.3, 0.7, 1.0] {
+            let rgb = film.reflectance(cos_theta, None);
+            let average =
+                spectral_average(|wavelength| film.reflectance(cos_theta, Some(wavelength)));
+            assert_near(rgb, average, 1e-2);
+        }
+
+        // The bands of a gray metal add up to the whole spectrum.
+        let film = ConductorFilm::new(ThinFilm::new(100.0, 1.5));
+        let albedo = Color::new(0.6, 0.6, 0.6);
+        for cos_theta in [0.3, 0.7, 1.0] {
+            let rgb = film.reflectance(cos_theta, albedo, None);
+            let average = spectral_average(|wavelength| {
+                film.reflectance(cos_theta, albedo, Some(wavelength))
+            });
+            assert_near(rgb, average, 1e-2);
+        }
+    }
+
+    #[test]
+    fn a_film_without_thickness_leaves_the_metal() {
+        // Head on, the metal reflects its albedo, up to how nearly upsampled colors come back.
+        let film = ConductorFilm::new(ThinFilm::new(0.0, 1.5));
+        let albedo = Color::new(0.9, 0.6, 0.3);
+        assert_near(film.reflectance(1.0, albedo, None), albedo, 5e-2);
+        let r = film.reflectance(1.0, Color::new(0.6, 0.6, 0.6), Some(550.0));
+        assert_near(r, Color::new(0.6, 0.6, 0.6), 1e-9);
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    matrix::Mat4,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// An instance of an object placed in the world by an affine transform. The object itself stays
+// in its own coordinate system, so several instances can share it.
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    matrix: Mat4,
+    inverse: Mat4,
+    // Transforms object space normals to world space.
+    normal_matrix: Mat4,
+    bbox: Aabb,
+}
+
+impl Transform {
+    pub fn new(object: Arc<dyn Hittable>, matrix: Mat4) -> Option<Self> {
+        // Returns None if the matrix can't be inverted, e.g. because it scales by zero.
+        let inverse = matrix.inverse()?;
+
+        // The world space bounding box encloses all eight transformed corners of the object's
+        // own bounding box.
+        let object_bbox = object.bounding_box();
+        let bbox = if object_bbox.x.size() < 0.0
+            || object_bbox.y.size() < 0.0
+            || object_bbox.z.size() < 0.0
+        {
+            Aabb::EMPTY
+        } else {
+            let mut bbox = Aabb::EMPTY;
+            for i in 0..8 {
+                let corner = Point3::new(
+                    if i & 1 == 0 {
+                        object_bbox.x.min
+                    } else {
+                        object_bbox.x.max
+                    },
+                    if i & 2 == 0 {
+                        object_bbox.y.min
+                    } else {
+                        object_bbox.y.max
+                    },
+                    if i & 4 == 0 {
+                        object_bbox.z.min
+                    } else {
+                        object_bbox.z.max
+                    },
+                );
+                let p = matrix.transform_point(corner);
+                bbox = Aabb::from_boxes(&bbox, &Aabb::from_points(p, p));
+            }
+            bbox
+        };
+
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            bbox,
+        })
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // Transform the ray into object space. The direction is not normalized, so the ray
+        // parameter t means the same in both spaces.
+        let object_r = Ray::with_time(
+            self.inverse.transform_point(r.origin()),
+            self.inverse.transform_vector(r.direction()),
+            r.time(),
+        );
+
+        // Determine whether an intersection exists in object space (and if so, where).
+        if !self.object.hit(&object_r, ray_t, rec) {
+            return false;
+        }
+
+        // Transform the intersection from object space back to world space. Normals transform
+        // by the inverse transpose, which keeps them perpendicular to the surface and on the
+        // same side of it as the ray, so front_face stays valid.
+        rec.p = self.matrix.transform_point(rec.p);
+        rec.normal = self.normal_matrix.transform_vector(rec.normal).normalized();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        // Map the direction into object space, where the object knows its own density, then
+        // account for how the transform stretches solid angles: a unit direction w maps to
+        // Bw/|Bw| with B the inverse matrix, whose solid angle Jacobian is |det B| / |Bw|^3.
+        let object_direction = self.inverse.transform_vector(direction.normalized());
+        let object_pdf = self
+            .object
+            .pdf_value(self.inverse.transform_point(origin), object_direction);
+        if object_pdf == 0.0 {
+            return 0.0;
+        }
+
+        object_pdf * self.inverse.linear_determinant().abs() / object_direction.length().powi(3)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        let object_direction = self.object.random(self.inverse.transform_point(origin));
+        self.matrix.transform_vector(object_direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian,
+        sphere::Sphere,
+        vec3::{Color, Vec3},
+    };
+
+    fn hit(object: &impl Hittable, r: &Ray) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        object
+            .hit(r, Interval::from_values(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, mat))
+    }
+
+    #[test]
+    fn instance_hit() {
+        // The unit sphere stretched into an ellipsoid along x, then moved to z = -5.
+        let matrix = Mat4::translation(Vec3::new(0.0, 0.0, -5.0))
+            * Mat4::rotation_y(90.0)
+            * Mat4::scaling(Vec3::new(1.0, 1.0, 2.0));
+        let instance = Transform::new(unit_sphere(), matrix).unwrap();
+
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = hit(&instance, &r).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert!((rec.p - Point3::new(0.0, 0.0, -4.0)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!(rec.front_face);
+
+        // The rotation turned the long axis of the ellipsoid along x.
+        let side = Ray::new(Point3::new(1.5, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = hit(&instance, &side).unwrap();
+        assert!((rec.p.y() - (1.0 - 0.5625f64).sqrt()).abs() < 1e-9);
+        assert!(rec.normal.x() > 0.0 && rec.normal.y() > 0.0);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+        let beside = Ray::new(Point3::new(0.0, 5.0, -6.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(hit(&instance, &beside).is_none());
+    }
+
+    #[test]
+    fn bounding_box_encloses_the_instance() {
+        let matrix = Mat4::translation(Vec3::new(10.0, 0.0, 0.0)) * Mat4::rotation_z(45.0);
+        let bbox = Transform::new(unit_sphere(), matrix)
+            .unwrap()
+            .bounding_box();
+        // The corners of the unit cube around the sphere reach out to sqrt(2) along x and y.
+        // Boxes are padded slightly, so only check that they are tight up to that.
+        let r = 2.0f64.sqrt();
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-3;
+        assert!(near(bbox.x.min, 10.0 - r) && near(bbox.x.max, 10.0 + r));
+        assert!(near(bbox.y.min, -r) && near(bbox.y.max, r));
+        assert!(near(bbox.z.min, -1.0) && near(bbox.z.max, 1.0));
+    }
+
+    #[test]
+    fn singular_transforms_are_rejected() {
+        let flat = Mat4::scaling(Vec3::new(1.0, 0.0, 1.0));
+        assert!(Transform::new(unit_sphere(), flat).is_none());
+    }
+}
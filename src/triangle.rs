@@ -0,0 +1,599 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    bvh,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::*,
+    random::random_double,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Texture coordinates used when a triangle has none of its own.
+const DEFAULT_UVS: [[f64; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+// Leaves of the mesh BVH hold at most this many faces.
+const MAX_FACES_PER_LEAF: usize = 4;
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [[f64; 2]; 3],
+    mat: Arc<dyn Scatter>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, mat: Arc<dyn Scatter>) -> Self {
+        Triangle {
+            vertices: [p0, p1, p2],
+            normals: None,
+            uvs: DEFAULT_UVS,
+            mat,
+            bbox: triangle_bounding_box(&[p0, p1, p2]),
+        }
+    }
+
+    pub fn with_vertex_data(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        mat: Arc<dyn Scatter>,
+    ) -> Self {
+        Triangle {
+            vertices,
+            normals,
+            uvs: uvs.unwrap_or(DEFAULT_UVS),
+            mat,
+            bbox: triangle_bounding_box(&vertices),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let Some((t, b1, b2)) = intersect_triangle(r, ray_t, &self.vertices) else {
+            return false;
+        };
+
+        set_hit_record(
+            r,
+            rec,
+            t,
+            [1.0 - b1 - b2, b1, b2],
+            &self.vertices,
+            self.normals.as_ref(),
+            &self.uvs,
+        );
+        rec.mat = self.mat.clone();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        let Some((t, _, _)) = intersect_triangle(
+            &r,
+            Interval::from_values(0.001, f64::INFINITY),
+            &self.vertices,
+        ) else {
+            return 0.0;
+        };
+
+        // Convert the uniform density over the area into a density over solid angle.
+        let n = (self.vertices[1] - self.vertices[0]).cross(self.vertices[2] - self.vertices[0]);
+        let area = 0.5 * n.length();
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.dot(n) / (direction.length() * n.length())).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        // Pick a uniform point on the triangle, folding points beyond the diagonal back onto it.
+        let (mut b1, mut b2) = (random_double(), random_double());
+        if b1 + b2 > 1.0 {
+            (b1, b2) = (1.0 - b1, 1.0 - b2);
+        }
+        let p = self.vertices[0]
+            + b1 * (self.vertices[1] - self.vertices[0])
+            + b2 * (self.vertices[2] - self.vertices[0]);
+        p - origin
+    }
+}
+
+// A node of the flattened mesh BVH. Interior nodes store their right child's index, with the left
+// child immediately following them; leaves store a range of faces.
+struct MeshNode {
+    bbox: Aabb,
+    start: usize,
+    count: usize,
+    right: usize,
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    IndexOutOfRange {
+        face: usize,
+        index: usize,
+        positions: usize,
+    },
+    NormalCount {
+        normals: usize,
+        positions: usize,
+    },
+    UvCount {
+        uvs: usize,
+        positions: usize,
+    },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::IndexOutOfRange {
+                face,
+                index,
+                positions,
+            } => write!(
+                f,
+                "face {face} uses vertex {index}, but the mesh has {positions} vertices"
+            ),
+            MeshError::NormalCount { normals, positions } => {
+                write!(f, "the mesh has {normals} normals for {positions} vertices")
+            }
+            MeshError::UvCount { uvs, positions } => write!(
+                f,
+                "the mesh has {uvs} texture coordinates for {positions} vertices"
+            ),
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<[f64; 2]>,
+    faces: Vec<[usize; 3]>,
+    mat: Arc<dyn Scatter>,
+    nodes: Vec<MeshNode>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<[f64; 2]>,
+        faces: Vec<[usize; 3]>,
+        mat: Arc<dyn Scatter>,
+    ) -> Result<Self, MeshError> {
+        // Normals and texture coordinates are optional, but when present they are indexed by
+        // the same vertex indices as the positions.
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(MeshError::NormalCount {
+                normals: normals.len(),
+                positions: positions.len(),
+            });
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return Err(MeshError::UvCount {
+                uvs: uvs.len(),
+                positions: positions.len(),
+            });
+        }
+        for (face, indices) in faces.iter().enumerate() {
+            if let Some(&index) = indices.iter().find(|&&i| i >= positions.len()) {
+                return Err(MeshError::IndexOutOfRange {
+                    face,
+                    index,
+                    positions: positions.len(),
+                });
+            }
+        }
+
+        let mut mesh = TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            faces,
+            mat,
+            nodes: Vec::new(),
+        };
+        mesh.build_bvh();
+        Ok(mesh)
+    }
+
+    fn face_vertices(&self, face: &[usize; 3]) -> [Point3; 3] {
+        [
+            self.positions[face[0]],
+            self.positions[face[1]],
+            self.positions[face[2]],
+        ]
+    }
+
+    fn build_bvh(&mut self) {
+        let mut faces = std::mem::take(&mut self.faces);
+        let mut nodes = Vec::with_capacity(2 * faces.len() / MAX_FACES_PER_LEAF + 1);
+        self.build_node(&mut faces, 0, &mut nodes);
+        self.faces = faces;
+        self.nodes = nodes;
+    }
+
+    fn build_node(&self, faces: &mut [[usize; 3]], start: usize, nodes: &mut Vec<MeshNode>) {
+        let face_box = |face: &[usize; 3]| triangle_bounding_box(&self.face_vertices(face));
+        let bbox = faces.iter().fold(Aabb::EMPTY, |bbox, face| {
+            Aabb::from_boxes(&bbox, &face_box(face))
+        });
+
+        let index = nodes.len();
+        nodes.push(MeshNode {
+            bbox,
+            start,
+            count: faces.len(),
+            right: 0,
+        });
+        if faces.len() <= MAX_FACES_PER_LEAF {
+            return;
+        }
+
+        let mid = bvh::partition(faces, face_box);
+        let (left_faces, right_faces) = faces.split_at_mut(mid);
+        self.build_node(left_faces, start, nodes);
+        nodes[index].right = nodes.len();
+        nodes[index].count = 0;
+        self.build_node(right_faces, start + mid, nodes);
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut closest: Option<(usize, f64, f64, f64)> = None;
+        let mut closest_so_far = ray_t.max;
+
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let node_t = Interval::from_values(ray_t.min, closest_so_far);
+            if !node.bbox.hit(r, node_t) {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(node.right);
+                stack.push(index + 1);
+                continue;
+            }
+
+            for face_index in node.start..node.start + node.count {
+                let vertices = self.face_vertices(&self.faces[face_index]);
+                let face_t = Interval::from_values(ray_t.min, closest_so_far);
+                if let Some((t, b1, b2)) = intersect_triangle(r, face_t, &vertices) {
+                    closest_so_far = t;
+                    closest = Some((face_index, t, b1, b2));
+                }
+            }
+        }
+
+        let Some((face_index, t, b1, b2)) = closest else {
+            return false;
+        };
+
+        let face = &self.faces[face_index];
+        let normals = (!self.normals.is_empty()).then(|| {
+            [
+                self.normals[face[0]],
+                self.normals[face[1]],
+                self.normals[face[2]],
+            ]
+        });
+        let uvs = if self.uvs.is_empty() {
+            DEFAULT_UVS
+        } else {
+            [self.uvs[face[0]], self.uvs[face[1]], self.uvs[face[2]]]
+        };
+        set_hit_record(
+            r,
+            rec,
+            t,
+            [1.0 - b1 - b2, b1, b2],
+            &self.face_vertices(face),
+            normals.as_ref(),
+            &uvs,
+        );
+        rec.mat = self.mat.clone();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+}
+
+fn triangle_bounding_box(vertices: &[Point3; 3]) -> Aabb {
+    Aabb::from_boxes(
+        &Aabb::from_points(vertices[0], vertices[1]),
+        &Aabb::from_points(vertices[0], vertices[2]),
+    )
+}
+
+fn intersect_triangle(r: &Ray, ray_t: Interval, vertices: &[Point3; 3]) -> Option<(f64, f64, f64)> {
+    // Möller–Trumbore ray/triangle intersection. Returns the ray parameter along with the
+    // barycentric coordinates of the hit point relative to the second and third vertex.
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    let pvec = r.direction().cross(edge2);
+    let det = edge1.dot(pvec);
+    // The ray is parallel to the triangle's plane. The determinant scales with the lengths of
+    // the edges and the direction, so the cutoff does too, which keeps tiny triangles visible.
+    let scale = r.direction().length_squared() * edge1.length_squared() * edge2.length_squared();
+    if det * det <= 1e-24 * scale {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = r.origin() - vertices[0];
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = r.direction().dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+fn set_hit_record(
+    r: &Ray,
+    rec: &mut HitRecord,
+    t: f64,
+    bary: [f64; 3],
+    vertices: &[Point3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: &[[f64; 2]; 3],
+) {
+    rec.t = t;
+    rec.p = r.at(t);
+    rec.u = bary[0] * uvs[0][0] + bary[1] * uvs[1][0] + bary[2] * uvs[2][0];
+    rec.v = bary[0] * uvs[0][1] + bary[1] * uvs[1][1] + bary[2] * uvs[2][1];
+
+    // The geometric normal decides which side of the surface was hit, while the interpolated
+    // vertex normal, when present, is used for shading on that same side.
+    let geometric_normal = (vertices[1] - vertices[0])
+        .cross(vertices[2] - vertices[0])
+        .normalized();
+    rec.set_face_normal(r, &geometric_normal);
+
+    if let Some(normals) = normals {
+        let shading_normal = bary[0] * normals[0] + bary[1] * normals[1] + bary[2] * normals[2];
+        if !shading_normal.near_zero() {
+            let shading_normal = shading_normal.normalized();
+            rec.normal = if shading_normal.dot(rec.normal) < 0.0 {
+                -shading_normal
+            } else {
+                shading_normal
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Color;
+
+    fn material() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn hit(object: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        object
+            .hit(
+                &Ray::new(origin, direction),
+                Interval::from_values(0.001, f64::INFINITY),
+                &mut rec,
+            )
+            .then_some(rec)
+    }
+
+    fn down_at(x: f64, y: f64) -> (Point3, Vec3) {
+        (Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    fn grid(n: usize) -> (Vec<Point3>, Vec<[usize; 3]>) {
+        // A unit square in the z = 0 plane split into 2 n² triangles.
+        let positions = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| Point3::new(i as f64, j as f64, 0.0) / n as f64))
+            .collect();
+        let mut faces = Vec::new();
+        for j in 0..n {
+            for i in 0..n {
+                let corner = j * (n + 1) + i;
+                faces.push([corner, corner + 1, corner + n + 2]);
+                faces.push([corner, corner + n + 2, corner + n + 1]);
+            }
+        }
+        (positions, faces)
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            material(),
+        );
+        let (origin, direction) = down_at(0.25, 0.5);
+        let rec = hit(&triangle, origin, direction).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(0.25, 0.5, 0.0)).length() < 1e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal.z(), 1.0);
+        // The default texture coordinates follow the barycentric coordinates.
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+
+        let rec = hit(&triangle, Point3::new(0.25, 0.25, -1.0), -direction).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal.z(), -1.0);
+
+        let (origin, direction) = down_at(0.6, 0.6);
+        assert!(hit(&triangle, origin, direction).is_none());
+        let parallel = Vec3::new(1.0, 0.0, 0.0);
+        assert!(hit(&triangle, Point3::new(-1.0, 0.25, 0.0), parallel).is_none());
+    }
+
+    #[test]
+    fn tiny_triangle_hit() {
+        let size = 1e-7;
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(size, 0.0, 0.0),
+            Point3::new(0.0, size, 0.0),
+            material(),
+        );
+        let (origin, direction) = down_at(0.25 * size, 0.25 * size);
+        assert!(hit(&triangle, origin, direction).is_some());
+    }
+
+    #[test]
+    fn vertex_data_is_interpolated() {
+        let triangle = Triangle::with_vertex_data(
+            [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            Some([
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(1.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ]),
+            Some([[0.5, 0.5], [1.0, 0.5], [0.5, 1.0]]),
+            material(),
+        );
+        let (origin, direction) = down_at(0.5, 0.25);
+        let rec = hit(&triangle, origin, direction).unwrap();
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.625).abs() < 1e-12);
+        let expected = Vec3::new(0.5, 0.0, 1.0).normalized();
+        assert!((rec.normal - expected).length() < 1e-12);
+    }
+
+    #[test]
+    fn mesh_matches_its_triangles() {
+        // Enough faces for the mesh BVH to have interior nodes.
+        let (positions, faces) = grid(8);
+        let uvs = positions.iter().map(|p| [p.x(), p.y()]).collect();
+        let triangles: Vec<Triangle> = faces
+            .iter()
+            .map(|f| {
+                Triangle::new(
+                    positions[f[0]],
+                    positions[f[1]],
+                    positions[f[2]],
+                    material(),
+                )
+            })
+            .collect();
+        let mesh = TriangleMesh::new(positions, Vec::new(), uvs, faces, material()).unwrap();
+        assert_eq!(mesh.faces.len(), 128);
+        let bbox = mesh.bounding_box();
+        // Boxes may be padded a little, but no more.
+        for (axis, max) in [(bbox.x, 1.0), (bbox.y, 1.0), (bbox.z, 0.0)] {
+            assert!(axis.min <= 0.0 && axis.min > -1e-3);
+            assert!(axis.max >= max && axis.max < max + 1e-3);
+        }
+
+        for j in 0..20 {
+            for i in 0..20 {
+                let (x, y) = ((i as f64 + 0.3) / 20.0, (j as f64 + 0.6) / 20.0);
+                let (origin, direction) = down_at(x, y);
+                let direction = direction + Vec3::new(0.01 * i as f64, -0.02, 0.0);
+                let expected = triangles
+                    .iter()
+                    .filter_map(|t| hit(t, origin, direction))
+                    .min_by(|a, b| a.t.total_cmp(&b.t));
+                let Some(rec) = hit(&mesh, origin, direction) else {
+                    assert!(expected.is_none());
+                    continue;
+                };
+                assert!((rec.t - expected.unwrap().t).abs() < 1e-12);
+                // The texture coordinates are those of the point hit.
+                assert!((rec.u - rec.p.x()).abs() < 1e-9 && (rec.v - rec.p.y()).abs() < 1e-9);
+            }
+        }
+        let (origin, direction) = down_at(1.5, 0.5);
+        assert!(hit(&mesh, origin, direction).is_none());
+    }
+
+    #[test]
+    fn mesh_closest_hit_wins() {
+        // Two stacked squares, the nearer one listed last.
+        let (mut positions, mut faces) = grid(1);
+        let (upper, upper_faces) = grid(1);
+        positions.extend(upper.iter().map(|&p| p + Vec3::new(0.0, 0.0, 0.5)));
+        faces.extend(upper_faces.iter().map(|f| f.map(|i| i + 4)));
+        let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), faces, material()).unwrap();
+        let (origin, direction) = down_at(0.3, 0.4);
+        assert!((hit(&mesh, origin, direction).unwrap().t - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn invalid_meshes_are_rejected() {
+        let (positions, faces) = grid(1);
+        let error = TriangleMesh::new(
+            positions.clone(),
+            Vec::new(),
+            Vec::new(),
+            vec![[0, 1, 2], [0, 4, 1]],
+            material(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "face 1 uses vertex 4, but the mesh has 4 vertices"
+        );
+
+        let normals = vec![Vec3::new(0.0, 0.0, 1.0); 3];
+        let error = TriangleMesh::new(
+            positions.clone(),
+            normals,
+            Vec::new(),
+            faces.clone(),
+            material(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.to_string(), "the mesh has 3 normals for 4 vertices");
+
+        let error = TriangleMesh::new(positions, Vec::new(), vec![[0.0, 0.0]], faces, material())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the mesh has 1 texture coordinates for 4 vertices"
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use core::f64;
+use std::f64::consts::PI;
 use std::fmt;
 use std::fmt::Display;
 use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
 
-use rand::{thread_rng, Rng};
+use crate::random::{random_double, random_double_range};
 
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct Vec3 {
@@ -27,14 +28,14 @@ impl Vec3 {
     }
 
     pub fn random() -> Self {
-        Self::new(thread_rng().gen(), thread_rng().gen(), thread_rng().gen())
+        Self::new(random_double(), random_double(), random_double())
     }
 
     pub fn random_in_range(min: f64, max: f64) -> Self {
         Self::new(
-            thread_rng().gen_range(min..max),
-            thread_rng().gen_range(min..max),
-            thread_rng().gen_range(min..max),
+            random_double_range(min, max),
+            random_double_range(min, max),
+            random_double_range(min, max),
         )
     }
 
@@ -102,11 +103,25 @@ impl Vec3 {
         }
     }
 
+    pub fn random_cosine_direction() -> Vec3 {
+        // Returns a direction on the hemisphere around +z, with a density proportional to the
+        // cosine of its angle to the z axis.
+        let r1 = random_double();
+        let r2 = random_double();
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
     pub fn random_in_unit_disk() -> Vec3 {
         loop {
             let p = Vec3::new(
-                rand::thread_rng().gen_range(-1.0..1.0),
-                thread_rng().gen_range(-1.0..1.0),
+                random_double_range(-1.0, 1.0),
+                random_double_range(-1.0, 1.0),
                 0.0,
             );
             if p.length_squared() < 1.0 {
@@ -140,7 +155,7 @@ impl Vec3 {
     pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
         let cos_theta = f64::min(n.dot(-*uv), 1.0);
         let r_out_perp: Vec3 = etai_over_etat * (*uv + cos_theta * *n);
-        let r_out_parallel: Vec3 = (-1.0 * f64::abs(1.0 - r_out_perp.length_squared()).sqrt()) * *n;
+        let r_out_parallel: Vec3 = -f64::abs(1.0 - r_out_perp.length_squared()).sqrt() * *n;
         r_out_perp + r_out_parallel
     }
 }
@@ -195,7 +210,7 @@ impl Add<Vec3> for f64 {
 }
 
 impl AddAssign for Vec3 {
-    fn add_assign(&mut self, other: Vec3) -> () {
+    fn add_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] + other[0], self[1] + other[1], self[2] + other[2]],
         };
@@ -213,7 +228,7 @@ impl Sub for Vec3 {
 }
 
 impl SubAssign for Vec3 {
-    fn sub_assign(&mut self, other: Vec3) -> () {
+    fn sub_assign(&mut self, other: Vec3) {
         *self = Vec3 {
             e: [self[0] - other[0], self[1] - other[1], self[2] - other[2]],
         };
@@ -231,7 +246,7 @@ impl Mul<f64> for Vec3 {
 }
 
 impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, other: f64) -> () {
+    fn mul_assign(&mut self, other: f64) {
         *self = Vec3 {
             e: [self[0] * other, self[1] * other, self[2] * other],
         };
@@ -269,7 +284,7 @@ impl Div<f64> for Vec3 {
 }
 
 impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, other: f64) -> () {
+    fn div_assign(&mut self, other: f64) {
         *self = Vec3 {
             e: [self[0] / other, self[1] / other, self[2] / other],
         };